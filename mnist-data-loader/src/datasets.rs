@@ -0,0 +1,486 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use crate::{open_idx, parse_imgs_from_reader, parse_labels_from_reader, IdxError, MmapDataset};
+
+/// 支持的MNIST格式数据集
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DatasetKind {
+    Mnist,
+    FashionMnist,
+    Kmnist,
+    EmnistBalanced,
+    EmnistLetters,
+    EmnistDigits,
+    EmnistByclass,
+}
+
+/// 数据集的文件名, 类别等信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatasetInfo {
+    pub name: &'static str,
+    pub train_images: &'static str,
+    pub train_labels: &'static str,
+    pub test_images: &'static str,
+    pub test_labels: &'static str,
+    // 下标就是类别编号
+    pub classes: &'static [&'static str],
+    // EMNIST的图片是转置存储的, 读取时要转回来
+    pub transposed: bool,
+    // 文件里的类别编号减去它才从0开始, EMNIST letters的类别是1..=26
+    pub label_offset: u8,
+}
+
+impl DatasetInfo {
+    pub fn num_classes(&self) -> usize {
+        self.classes.len()
+    }
+}
+
+const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+const FASHION: [&str; 10] = [
+    "T-shirt/top",
+    "Trouser",
+    "Pullover",
+    "Dress",
+    "Coat",
+    "Sandal",
+    "Shirt",
+    "Sneaker",
+    "Bag",
+    "Ankle boot",
+];
+
+const KMNIST: [&str; 10] = ["お", "き", "す", "つ", "な", "は", "ま", "や", "れ", "を"];
+
+const LETTERS: [&str; 26] = [
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S",
+    "T", "U", "V", "W", "X", "Y", "Z",
+];
+
+// 数字, 大写字母, 以及和大写写法不同的11个小写字母
+const BALANCED: [&str; 47] = [
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "A", "B", "C", "D", "E", "F", "G", "H", "I",
+    "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z", "a", "b",
+    "d", "e", "f", "g", "h", "n", "q", "r", "t",
+];
+
+const BYCLASS: [&str; 62] = [
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "A", "B", "C", "D", "E", "F", "G", "H", "I",
+    "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z", "a", "b",
+    "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s", "t", "u",
+    "v", "w", "x", "y", "z",
+];
+
+// 各数据集官方发布的文件名(解压后)
+const fn emnist(
+    name: &'static str,
+    files: [&'static str; 4],
+    classes: &'static [&'static str],
+    label_offset: u8,
+) -> DatasetInfo {
+    DatasetInfo {
+        name,
+        train_images: files[0],
+        train_labels: files[1],
+        test_images: files[2],
+        test_labels: files[3],
+        classes,
+        transposed: true,
+        label_offset,
+    }
+}
+
+const MNIST_INFO: DatasetInfo = DatasetInfo {
+    name: "mnist",
+    train_images: "train-images.idx3-ubyte",
+    train_labels: "train-labels.idx1-ubyte",
+    test_images: "t10k-images.idx3-ubyte",
+    test_labels: "t10k-labels.idx1-ubyte",
+    classes: &DIGITS,
+    transposed: false,
+    label_offset: 0,
+};
+
+const FASHION_INFO: DatasetInfo = DatasetInfo {
+    name: "fashion-mnist",
+    train_images: "train-images-idx3-ubyte",
+    train_labels: "train-labels-idx1-ubyte",
+    test_images: "t10k-images-idx3-ubyte",
+    test_labels: "t10k-labels-idx1-ubyte",
+    classes: &FASHION,
+    transposed: false,
+    label_offset: 0,
+};
+
+const KMNIST_INFO: DatasetInfo = DatasetInfo {
+    name: "kmnist",
+    classes: &KMNIST,
+    ..FASHION_INFO
+};
+
+const EMNIST_BALANCED: DatasetInfo = emnist(
+    "emnist-balanced",
+    [
+        "emnist-balanced-train-images-idx3-ubyte",
+        "emnist-balanced-train-labels-idx1-ubyte",
+        "emnist-balanced-test-images-idx3-ubyte",
+        "emnist-balanced-test-labels-idx1-ubyte",
+    ],
+    &BALANCED,
+    0,
+);
+
+const EMNIST_LETTERS: DatasetInfo = emnist(
+    "emnist-letters",
+    [
+        "emnist-letters-train-images-idx3-ubyte",
+        "emnist-letters-train-labels-idx1-ubyte",
+        "emnist-letters-test-images-idx3-ubyte",
+        "emnist-letters-test-labels-idx1-ubyte",
+    ],
+    &LETTERS,
+    1,
+);
+
+const EMNIST_DIGITS: DatasetInfo = emnist(
+    "emnist-digits",
+    [
+        "emnist-digits-train-images-idx3-ubyte",
+        "emnist-digits-train-labels-idx1-ubyte",
+        "emnist-digits-test-images-idx3-ubyte",
+        "emnist-digits-test-labels-idx1-ubyte",
+    ],
+    &DIGITS,
+    0,
+);
+
+const EMNIST_BYCLASS: DatasetInfo = emnist(
+    "emnist-byclass",
+    [
+        "emnist-byclass-train-images-idx3-ubyte",
+        "emnist-byclass-train-labels-idx1-ubyte",
+        "emnist-byclass-test-images-idx3-ubyte",
+        "emnist-byclass-test-labels-idx1-ubyte",
+    ],
+    &BYCLASS,
+    0,
+);
+
+impl DatasetKind {
+    pub const ALL: [DatasetKind; 7] = [
+        DatasetKind::Mnist,
+        DatasetKind::FashionMnist,
+        DatasetKind::Kmnist,
+        DatasetKind::EmnistBalanced,
+        DatasetKind::EmnistLetters,
+        DatasetKind::EmnistDigits,
+        DatasetKind::EmnistByclass,
+    ];
+
+    pub fn info(self) -> &'static DatasetInfo {
+        match self {
+            DatasetKind::Mnist => &MNIST_INFO,
+            DatasetKind::FashionMnist => &FASHION_INFO,
+            DatasetKind::Kmnist => &KMNIST_INFO,
+            DatasetKind::EmnistBalanced => &EMNIST_BALANCED,
+            DatasetKind::EmnistLetters => &EMNIST_LETTERS,
+            DatasetKind::EmnistDigits => &EMNIST_DIGITS,
+            DatasetKind::EmnistByclass => &EMNIST_BYCLASS,
+        }
+    }
+
+    // 按名字查找, 比如 "fashion-mnist", "emnist-letters"
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.info().name == name)
+    }
+}
+
+/// 从本地目录读出的一个数据集, 图片已经转成正常方向, 类别从0开始
+pub struct Dataset {
+    pub info: &'static DatasetInfo,
+    pub rows: u32,
+    pub cols: u32,
+    pub train_images: Vec<Vec<u8>>,
+    pub train_labels: Vec<u8>,
+    pub test_images: Vec<Vec<u8>>,
+    pub test_labels: Vec<u8>,
+}
+
+impl Dataset {
+    // dir下要有DatasetInfo里的四个文件, 也可以是没解压的.gz文件
+    pub fn load(kind: DatasetKind, dir: impl AsRef<Path>) -> Result<Self, IdxError> {
+        let info = kind.info();
+        let dir = dir.as_ref();
+        let (rows, cols, train_images) = load_images(info, &dir.join(info.train_images))?;
+        let train_labels = load_labels(info, &dir.join(info.train_labels))?;
+        let (test_rows, test_cols, test_images) = load_images(info, &dir.join(info.test_images))?;
+        let test_labels = load_labels(info, &dir.join(info.test_labels))?;
+        if (rows, cols) != (test_rows, test_cols) {
+            return Err(IdxError::SizeMismatch {
+                train: (rows, cols),
+                test: (test_rows, test_cols),
+            });
+        }
+        for (images, labels) in [(&train_images, &train_labels), (&test_images, &test_labels)] {
+            if images.len() != labels.len() {
+                return Err(IdxError::CountMismatch {
+                    images: images.len(),
+                    labels: labels.len(),
+                });
+            }
+        }
+        Ok(Dataset {
+            info,
+            rows,
+            cols,
+            train_images,
+            train_labels,
+            test_images,
+            test_labels,
+        })
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.info.num_classes()
+    }
+
+    pub fn class_name(&self, label: u8) -> &'static str {
+        self.info.classes[label as usize]
+    }
+}
+
+/// 内存映射的数据集, 图片按需读取, 不把图片文件读进内存, 适合内存放不下的数据集
+/// 标签很小, 打开时读出来并检查范围; 只支持解压过的文件
+pub struct LazyDataset {
+    pub info: &'static DatasetInfo,
+    // 转成正常方向后的行数和列数
+    pub rows: u32,
+    pub cols: u32,
+    // 类别从0开始
+    pub train_labels: Vec<u8>,
+    pub test_labels: Vec<u8>,
+    train: MmapDataset,
+    test: MmapDataset,
+}
+
+impl LazyDataset {
+    // dir下要有DatasetInfo里的四个文件
+    pub fn open(kind: DatasetKind, dir: impl AsRef<Path>) -> Result<Self, IdxError> {
+        let info = kind.info();
+        let dir = dir.as_ref();
+        // 出错时带上图片文件的路径, 图片和标签个数不一致时也是这个路径
+        let open = |images: &str, labels: &str| {
+            let images = dir.join(images);
+            MmapDataset::open(&images, dir.join(labels)).map_err(|e| e.in_file(&images))
+        };
+        let train = open(info.train_images, info.train_labels)?;
+        let test = open(info.test_images, info.test_labels)?;
+        if (train.rows(), train.cols()) != (test.rows(), test.cols()) {
+            return Err(IdxError::SizeMismatch {
+                train: (train.rows(), train.cols()),
+                test: (test.rows(), test.cols()),
+            });
+        }
+        let labels = |ds: &MmapDataset| {
+            let mut labels: Vec<u8> = ds.iter().map(|(_, l)| l).collect();
+            check_labels(info, &mut labels)?;
+            Ok::<_, IdxError>(labels)
+        };
+        let (rows, cols) = if info.transposed {
+            (train.cols(), train.rows())
+        } else {
+            (train.rows(), train.cols())
+        };
+        Ok(LazyDataset {
+            info,
+            rows,
+            cols,
+            train_labels: labels(&train)?,
+            test_labels: labels(&test)?,
+            train,
+            test,
+        })
+    }
+
+    // 第i张训练图片, 按行存储, 不需要转置时不复制
+    pub fn train_image(&self, i: usize) -> Cow<'_, [u8]> {
+        self.image(&self.train, i)
+    }
+
+    pub fn test_image(&self, i: usize) -> Cow<'_, [u8]> {
+        self.image(&self.test, i)
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.info.num_classes()
+    }
+
+    pub fn class_name(&self, label: u8) -> &'static str {
+        self.info.classes[label as usize]
+    }
+
+    fn image<'a>(&self, ds: &'a MmapDataset, i: usize) -> Cow<'a, [u8]> {
+        let img = ds.image(i);
+        if self.info.transposed {
+            Cow::Owned(transpose(img, ds.rows(), ds.cols()))
+        } else {
+            Cow::Borrowed(img)
+        }
+    }
+}
+
+fn load_images(info: &DatasetInfo, path: &Path) -> Result<(u32, u32, Vec<Vec<u8>>), IdxError> {
+    let mut f = open_idx(path)?;
+    let (rows, cols, imgs) = parse_imgs_from_reader(&mut f).map_err(|e| e.in_file(path))?;
+    if !info.transposed {
+        return Ok((rows, cols, imgs));
+    }
+    let imgs = imgs.iter().map(|img| transpose(img, rows, cols)).collect();
+    Ok((cols, rows, imgs))
+}
+
+fn load_labels(info: &DatasetInfo, path: &Path) -> Result<Vec<u8>, IdxError> {
+    let mut f = open_idx(path)?;
+    let mut labels = parse_labels_from_reader(&mut f).map_err(|e| e.in_file(path))?;
+    check_labels(info, &mut labels).map_err(|e| e.in_file(path))?;
+    Ok(labels)
+}
+
+// 检查类别编号的范围, 并减去label_offset使类别从0开始
+fn check_labels(info: &DatasetInfo, labels: &mut [u8]) -> Result<(), IdxError> {
+    for label in labels.iter_mut() {
+        let l = label.checked_sub(info.label_offset).map(|l| l as usize);
+        if l.is_none_or(|l| l >= info.num_classes()) {
+            return Err(IdxError::LabelOutOfRange {
+                dataset: info.name,
+                label: *label,
+            });
+        }
+        *label -= info.label_offset;
+    }
+    Ok(())
+}
+
+// rows行cols列的图片转置为cols行rows列
+pub fn transpose(img: &[u8], rows: u32, cols: u32) -> Vec<u8> {
+    let (rows, cols) = (rows as usize, cols as usize);
+    let mut out = vec![0; img.len()];
+    for r in 0..rows {
+        for c in 0..cols {
+            out[c * rows + r] = img[r * cols + c];
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::Path;
+
+    use super::{transpose, Dataset, DatasetKind, LazyDataset};
+    use crate::IdxError;
+
+    fn write_images(path: &Path, rows: u32, cols: u32, imgs: &[Vec<u8>]) {
+        let mut f = std::fs::File::create(path).unwrap();
+        for v in [2051, imgs.len() as u32, rows, cols] {
+            f.write_all(&v.to_be_bytes()).unwrap();
+        }
+        for img in imgs {
+            f.write_all(img).unwrap();
+        }
+    }
+
+    fn write_labels(path: &Path, labels: &[u8]) {
+        let mut f = std::fs::File::create(path).unwrap();
+        for v in [2049, labels.len() as u32] {
+            f.write_all(&v.to_be_bytes()).unwrap();
+        }
+        f.write_all(labels).unwrap();
+    }
+
+    #[test]
+    fn test_info() {
+        let counts: Vec<usize> = DatasetKind::ALL
+            .iter()
+            .map(|k| k.info().num_classes())
+            .collect();
+        assert_eq!(counts, vec![10, 10, 10, 47, 26, 10, 62]);
+        assert_eq!(
+            DatasetKind::from_name("emnist-letters"),
+            Some(DatasetKind::EmnistLetters)
+        );
+        assert_eq!(DatasetKind::FashionMnist.info().classes[9], "Ankle boot");
+        assert_eq!(transpose(&[1, 2, 3, 4, 5, 6], 2, 3), vec![1, 4, 2, 5, 3, 6]);
+    }
+
+    #[test]
+    fn test_load_emnist() {
+        let dir = std::env::temp_dir().join(format!("mnist-loader-emnist-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let info = DatasetKind::EmnistLetters.info();
+        // 文件里存的是转置后的2行3列图片
+        let img = vec![1, 2, 3, 4, 5, 6];
+        write_images(
+            &dir.join(info.train_images),
+            2,
+            3,
+            &[img.clone(), img.clone()],
+        );
+        write_labels(&dir.join(info.train_labels), &[1, 26]);
+        write_images(&dir.join(info.test_images), 2, 3, &[img]);
+        write_labels(&dir.join(info.test_labels), &[3]);
+
+        let ds = Dataset::load(DatasetKind::EmnistLetters, &dir).unwrap();
+        assert_eq!((ds.rows, ds.cols), (3, 2));
+        assert_eq!(ds.train_images[0], vec![1, 4, 2, 5, 3, 6]);
+        assert_eq!(ds.train_labels, vec![0, 25]);
+        assert_eq!(ds.class_name(ds.test_labels[0]), "C");
+
+        // 内存映射读出的结果和一次读入的一致
+        let lazy = LazyDataset::open(DatasetKind::EmnistLetters, &dir).unwrap();
+        assert_eq!((lazy.rows, lazy.cols), (3, 2));
+        assert_eq!(&*lazy.train_image(1), &ds.train_images[1][..]);
+        assert_eq!(&*lazy.test_image(0), &ds.test_images[0][..]);
+        assert_eq!(lazy.train_labels, ds.train_labels);
+        assert_eq!(lazy.test_labels, ds.test_labels);
+
+        // letters里没有0这个类别
+        write_labels(&dir.join(info.test_labels), &[0]);
+        let err = Dataset::load(DatasetKind::EmnistLetters, &dir)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            IdxError::InFile { ref error, .. }
+                if matches!(**error, IdxError::LabelOutOfRange { label: 0, .. })
+        ));
+        assert!(matches!(
+            LazyDataset::open(DatasetKind::EmnistLetters, &dir),
+            Err(IdxError::LabelOutOfRange { label: 0, .. })
+        ));
+
+        // 训练集和测试集的图片大小不一致
+        write_labels(&dir.join(info.test_labels), &[3]);
+        write_images(&dir.join(info.test_images), 3, 3, &[vec![0; 9]]);
+        assert!(matches!(
+            Dataset::load(DatasetKind::EmnistLetters, &dir),
+            Err(IdxError::SizeMismatch {
+                train: (3, 2),
+                test: (3, 3)
+            })
+        ));
+
+        // 内存映射不支持压缩文件
+        std::fs::write(dir.join(info.test_images), [0x1f, 0x8b, 8, 0]).unwrap();
+        let err = LazyDataset::open(DatasetKind::EmnistLetters, &dir)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, IdxError::InFile { ref error, .. } if matches!(**error, IdxError::Gzip))
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 解析idx文件时的错误
+#[derive(Debug)]
+pub enum IdxError {
+    Io(io::Error),
+    // 文件头前4个字节不对, expected为None时表示不是任何idx格式
+    BadMagic { expected: Option<u32>, actual: u32 },
+    // 不支持的元素类型
+    UnknownDtype(u8),
+    // 文件比文件头声明的短, 单位是字节
+    Truncated { expected: u64, actual: u64 },
+    // 各个维度相乘溢出
+    DimensionOverflow,
+    // 数据大小超过了IdxLimits里的限制
+    TooLarge { size: u64, limit: u64 },
+    // 图片和标签的个数不一致
+    CountMismatch { images: usize, labels: usize },
+    // 图片的行数或列数为0
+    EmptyImage { rows: usize, cols: usize },
+    // 像素个数不等于 行数 * 列数
+    ImageSize { expected: usize, actual: usize },
+    // 训练集和测试集的图片大小不一致, 都是(行, 列)
+    SizeMismatch { train: (u32, u32), test: (u32, u32) },
+    // 标签超出数据集的类别范围
+    LabelOutOfRange { dataset: &'static str, label: u8 },
+    // 内存映射不支持gzip压缩的文件
+    Gzip,
+    // 读取path时出错, 用于Dataset等一次读取多个文件的地方
+    InFile { path: PathBuf, error: Box<IdxError> },
+}
+
+impl IdxError {
+    pub(crate) fn in_file(self, path: &Path) -> Self {
+        IdxError::InFile {
+            path: path.to_path_buf(),
+            error: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for IdxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdxError::Io(e) => write!(f, "io error: {}", e),
+            IdxError::BadMagic {
+                expected: Some(expected),
+                actual,
+            } => write!(
+                f,
+                "bad magic number: expects 0x{:08x}, got 0x{:08x}",
+                expected, actual
+            ),
+            IdxError::BadMagic {
+                expected: None,
+                actual,
+            } => write!(f, "not an idx file, magic number 0x{:08x}", actual),
+            IdxError::UnknownDtype(t) => write!(f, "unknown idx dtype 0x{:02x}", t),
+            IdxError::Truncated { expected, actual } => write!(
+                f,
+                "file is truncated: expects {} bytes, got {}",
+                expected, actual
+            ),
+            IdxError::DimensionOverflow => write!(f, "dimensions overflow"),
+            IdxError::TooLarge { size, limit } => write!(
+                f,
+                "data size {} bytes exceeds the limit of {} bytes",
+                size, limit
+            ),
+            IdxError::CountMismatch { images, labels } => {
+                write!(f, "{} images but {} labels", images, labels)
+            }
+            IdxError::EmptyImage { rows, cols } => {
+                write!(f, "image size {}x{} is empty", rows, cols)
+            }
+            IdxError::ImageSize { expected, actual } => {
+                write!(f, "image has {} pixels, expects {}", actual, expected)
+            }
+            IdxError::SizeMismatch { train, test } => write!(
+                f,
+                "train images are {}x{}, test images are {}x{}",
+                train.0, train.1, test.0, test.1
+            ),
+            IdxError::LabelOutOfRange { dataset, label } => {
+                write!(f, "{}: label {} out of range", dataset, label)
+            }
+            IdxError::Gzip => write!(
+                f,
+                "file is gzip compressed, decompress it before memory mapping"
+            ),
+            IdxError::InFile { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for IdxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IdxError::Io(e) => Some(e),
+            IdxError::InFile { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for IdxError {
+    fn from(e: io::Error) -> Self {
+        IdxError::Io(e)
+    }
+}
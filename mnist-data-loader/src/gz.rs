@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::IdxError;
+
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// 开头是gzip魔数时边读边解压, 否则原样读取
+pub fn decompress<'a, R: Read + 'a>(reader: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+// 打开idx文件, 压缩过的文件会自动解压
+// path不存在时尝试 path.gz, 官方下载的文件不用手动解压
+pub fn open_idx(path: impl AsRef<Path>) -> Result<Box<dyn Read>, IdxError> {
+    let path = path.as_ref();
+    let gz = PathBuf::from(format!("{}.gz", path.display()));
+    let path = if !path.exists() && gz.exists() {
+        gz.as_path()
+    } else {
+        path
+    };
+    let f = File::open(path).map_err(|e| IdxError::from(e).in_file(path))?;
+    decompress(f).map_err(|e| IdxError::from(e).in_file(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::{decompress, open_idx};
+    use crate::{parse_labels_from_reader, read_idx, IdxError};
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(vec![], Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn test() {
+        let labels = [0, 0, 8, 1, 0, 0, 0, 3, 7, 2, 1];
+        let gz = gzip(&labels);
+
+        let mut out = vec![];
+        decompress(&gz[..]).unwrap().read_to_end(&mut out).unwrap();
+        assert_eq!(out, labels);
+        let mut out = vec![];
+        decompress(&labels[..])
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, labels);
+
+        assert_eq!(
+            parse_labels_from_reader(&mut &gz[..]).unwrap(),
+            vec![7, 2, 1]
+        );
+        assert_eq!(read_idx(&mut &gz[..]).unwrap().shape(), &[3]);
+
+        let dir = std::env::temp_dir().join(format!("mnist-loader-gz-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("labels-idx1-ubyte.gz"), &gz).unwrap();
+        let mut r = open_idx(dir.join("labels-idx1-ubyte")).unwrap();
+        assert_eq!(parse_labels_from_reader(&mut r).unwrap(), vec![7, 2, 1]);
+        let err = open_idx(dir.join("missing")).err().unwrap();
+        assert!(
+            matches!(err, IdxError::InFile { ref error, .. } if matches!(**error, IdxError::Io(_)))
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+use std::io::{self, Read, Write};
+
+use ndarray::{ArrayD, IxDyn};
+
+use crate::{decompress, IdxError};
+
+/// IDX文件支持的元素类型, 文件里都是大端存储
+pub trait IdxElem: Copy + 'static {
+    // 文件头第3个字节
+    const DTYPE: u8;
+    const SIZE: usize;
+    fn from_be(bytes: &[u8]) -> Self;
+    fn put_be(self, out: &mut Vec<u8>);
+    fn wrap(arr: ArrayD<Self>) -> IdxData;
+    fn unwrap(data: IdxData) -> Option<ArrayD<Self>>;
+}
+
+macro_rules! idx_elem {
+    ($t:ty, $dtype:expr, $variant:ident) => {
+        impl IdxElem for $t {
+            const DTYPE: u8 = $dtype;
+            const SIZE: usize = std::mem::size_of::<$t>();
+            fn from_be(bytes: &[u8]) -> Self {
+                <$t>::from_be_bytes(bytes.try_into().unwrap())
+            }
+            fn put_be(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+            fn wrap(arr: ArrayD<Self>) -> IdxData {
+                IdxData::$variant(arr)
+            }
+            fn unwrap(data: IdxData) -> Option<ArrayD<Self>> {
+                match data {
+                    IdxData::$variant(arr) => Some(arr),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+idx_elem!(u8, 0x08, U8);
+idx_elem!(i8, 0x09, I8);
+idx_elem!(i16, 0x0b, I16);
+idx_elem!(i32, 0x0c, I32);
+idx_elem!(f32, 0x0d, F32);
+idx_elem!(f64, 0x0e, F64);
+
+/// 一个IDX文件的内容, 形状就是文件头里的各个维度
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdxData {
+    U8(ArrayD<u8>),
+    I8(ArrayD<i8>),
+    I16(ArrayD<i16>),
+    I32(ArrayD<i32>),
+    F32(ArrayD<f32>),
+    F64(ArrayD<f64>),
+}
+
+impl IdxData {
+    pub fn shape(&self) -> &[usize] {
+        match self {
+            IdxData::U8(a) => a.shape(),
+            IdxData::I8(a) => a.shape(),
+            IdxData::I16(a) => a.shape(),
+            IdxData::I32(a) => a.shape(),
+            IdxData::F32(a) => a.shape(),
+            IdxData::F64(a) => a.shape(),
+        }
+    }
+
+    pub fn dtype(&self) -> u8 {
+        match self {
+            IdxData::U8(_) => u8::DTYPE,
+            IdxData::I8(_) => i8::DTYPE,
+            IdxData::I16(_) => i16::DTYPE,
+            IdxData::I32(_) => i32::DTYPE,
+            IdxData::F32(_) => f32::DTYPE,
+            IdxData::F64(_) => f64::DTYPE,
+        }
+    }
+
+    // 元素类型不是T时返回None
+    pub fn into_array<T: IdxElem>(self) -> Option<ArrayD<T>> {
+        T::unwrap(self)
+    }
+}
+
+/// 读取idx文件时的限制, 防止文件头里的维度导致分配过多内存
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdxLimits {
+    // 数据部分最多的字节数, 解压后计算
+    pub max_bytes: u64,
+}
+
+impl Default for IdxLimits {
+    // 默认2GB, 足够读取EMNIST byclass
+    fn default() -> Self {
+        IdxLimits { max_bytes: 2 << 30 }
+    }
+}
+
+impl IdxLimits {
+    pub fn unlimited() -> Self {
+        IdxLimits {
+            max_bytes: u64::MAX,
+        }
+    }
+}
+
+/// idx文件头
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdxHeader {
+    pub dtype: u8,
+    pub shape: Vec<usize>,
+}
+
+impl IdxHeader {
+    // 前4个字节组成的魔数, 比如图片文件是2051
+    pub fn magic(&self) -> u32 {
+        u32::from_be_bytes([0, 0, self.dtype, self.shape.len() as u8])
+    }
+
+    // 文件头的字节数
+    pub fn byte_len(&self) -> usize {
+        4 + self.shape.len() * 4
+    }
+
+    // 数据部分的字节数
+    pub fn body_len(&self) -> Result<u64, IdxError> {
+        let elem = elem_size(self.dtype).ok_or(IdxError::UnknownDtype(self.dtype))? as u64;
+        self.shape
+            .iter()
+            .try_fold(elem, |n, d| n.checked_mul(*d as u64))
+            .ok_or(IdxError::DimensionOverflow)
+    }
+}
+
+pub(crate) fn elem_size(dtype: u8) -> Option<usize> {
+    Some(match dtype {
+        u8::DTYPE => u8::SIZE,
+        i8::DTYPE => i8::SIZE,
+        i16::DTYPE => i16::SIZE,
+        i32::DTYPE => i32::SIZE,
+        f32::DTYPE => f32::SIZE,
+        f64::DTYPE => f64::SIZE,
+        _ => return None,
+    })
+}
+
+// 文件头: 两个0字节, 类型, 维数, 然后每个维度一个大端u32
+// 检查类型和数据大小, 返回文件头和数据部分的字节数
+pub fn read_header<R: Read>(
+    reader: &mut R,
+    limits: &IdxLimits,
+) -> Result<(IdxHeader, u64), IdxError> {
+    let mut magic = [0; 4];
+    read_exact(reader, &mut magic, 0, 4)?;
+    if magic[0] != 0 || magic[1] != 0 {
+        return Err(IdxError::BadMagic {
+            expected: None,
+            actual: u32::from_be_bytes(magic),
+        });
+    }
+    let ndim = magic[3] as usize;
+    let mut dims = vec![0; ndim * 4];
+    read_exact(reader, &mut dims, 4, 4 + ndim as u64 * 4)?;
+    let header = IdxHeader {
+        dtype: magic[2],
+        shape: dims
+            .chunks_exact(4)
+            .map(|d| u32::from_be_bytes(d.try_into().unwrap()) as usize)
+            .collect(),
+    };
+    let size = header.body_len()?;
+    if size > limits.max_bytes {
+        return Err(IdxError::TooLarge {
+            size,
+            limit: limits.max_bytes,
+        });
+    }
+    Ok((header, size))
+}
+
+// 读取size字节的数据, 按实际读到的数据扩容, 文件头声明的大小再大也不会提前分配
+pub(crate) fn read_body<R: Read>(
+    reader: &mut R,
+    header: &IdxHeader,
+    size: u64,
+) -> Result<Vec<u8>, IdxError> {
+    let mut body = Vec::with_capacity(size.min(1 << 20) as usize);
+    reader.take(size).read_to_end(&mut body)?;
+    if (body.len() as u64) < size {
+        return Err(IdxError::Truncated {
+            expected: header.byte_len() as u64 + size,
+            actual: (header.byte_len() + body.len()) as u64,
+        });
+    }
+    Ok(body)
+}
+
+// start: buf在文件中的位置, end: 文件至少应有的长度, 读不满时返回Truncated
+fn read_exact<R: Read>(
+    reader: &mut R,
+    buf: &mut [u8],
+    start: u64,
+    end: u64,
+) -> Result<(), IdxError> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => {
+                return Err(IdxError::Truncated {
+                    expected: end,
+                    actual: start + n as u64,
+                })
+            }
+            Ok(k) => n += k,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+// gzip压缩的数据会自动解压
+pub fn read_idx<R: Read>(reader: &mut R) -> Result<IdxData, IdxError> {
+    read_idx_with(reader, &IdxLimits::default())
+}
+
+pub fn read_idx_with<R: Read>(reader: &mut R, limits: &IdxLimits) -> Result<IdxData, IdxError> {
+    let mut reader = decompress(reader)?;
+    let (header, size) = read_header(&mut reader, limits)?;
+    let body = read_body(&mut reader, &header, size)?;
+    Ok(match header.dtype {
+        u8::DTYPE => to_array::<u8>(&header, &body),
+        i8::DTYPE => to_array::<i8>(&header, &body),
+        i16::DTYPE => to_array::<i16>(&header, &body),
+        i32::DTYPE => to_array::<i32>(&header, &body),
+        f32::DTYPE => to_array::<f32>(&header, &body),
+        f64::DTYPE => to_array::<f64>(&header, &body),
+        t => return Err(IdxError::UnknownDtype(t)),
+    })
+}
+
+fn to_array<T: IdxElem>(header: &IdxHeader, body: &[u8]) -> IdxData {
+    let data = body.chunks_exact(T::SIZE).map(T::from_be).collect();
+    T::wrap(ArrayD::from_shape_vec(IxDyn(&header.shape), data).unwrap())
+}
+
+pub fn write_idx<W: Write>(writer: &mut W, data: &IdxData) -> io::Result<()> {
+    match data {
+        IdxData::U8(a) => write_idx_array(writer, a),
+        IdxData::I8(a) => write_idx_array(writer, a),
+        IdxData::I16(a) => write_idx_array(writer, a),
+        IdxData::I32(a) => write_idx_array(writer, a),
+        IdxData::F32(a) => write_idx_array(writer, a),
+        IdxData::F64(a) => write_idx_array(writer, a),
+    }
+}
+
+// 按行优先的逻辑顺序写出, 不要求数组内存连续
+pub fn write_idx_array<T: IdxElem, W: Write>(writer: &mut W, arr: &ArrayD<T>) -> io::Result<()> {
+    if arr.ndim() > u8::MAX as usize || arr.shape().iter().any(|d| *d > u32::MAX as usize) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "array shape does not fit in idx header",
+        ));
+    }
+    let mut out = Vec::with_capacity(4 + arr.ndim() * 4 + arr.len() * T::SIZE);
+    out.extend_from_slice(&[0, 0, T::DTYPE, arr.ndim() as u8]);
+    for d in arr.shape() {
+        out.extend_from_slice(&(*d as u32).to_be_bytes());
+    }
+    for v in arr.iter() {
+        v.put_be(&mut out);
+    }
+    writer.write_all(&out)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{ArrayD, IxDyn};
+
+    use super::{read_idx, write_idx, write_idx_array, IdxData};
+
+    #[test]
+    fn test_round_trip() {
+        let datas = vec![
+            IdxData::U8(ArrayD::from_shape_vec(IxDyn(&[2, 3]), vec![0, 1, 2, 3, 4, 255]).unwrap()),
+            IdxData::I8(ArrayD::from_shape_vec(IxDyn(&[3]), vec![-128, 0, 127]).unwrap()),
+            IdxData::I16(ArrayD::from_shape_vec(IxDyn(&[1, 2, 1]), vec![-300, 300]).unwrap()),
+            IdxData::I32(ArrayD::from_shape_vec(IxDyn(&[2]), vec![i32::MIN, 7]).unwrap()),
+            IdxData::F32(ArrayD::from_shape_vec(IxDyn(&[2, 1]), vec![0.5, -1.25]).unwrap()),
+            IdxData::F64(ArrayD::from_shape_vec(IxDyn(&[1, 1, 1, 2]), vec![1e-300, 3.5]).unwrap()),
+            IdxData::U8(ArrayD::from_shape_vec(IxDyn(&[0, 4]), vec![]).unwrap()),
+        ];
+        for data in datas {
+            let mut buf = vec![];
+            write_idx(&mut buf, &data).unwrap();
+            assert_eq!(buf[2], data.dtype());
+            assert_eq!(buf[3] as usize, data.shape().len());
+            assert_eq!(read_idx(&mut &buf[..]).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        // 和MNIST标签文件一样的格式
+        let buf = [0, 0, 8, 1, 0, 0, 0, 3, 7, 2, 1];
+        let labels = read_idx(&mut &buf[..]).unwrap();
+        assert_eq!(labels.shape(), &[3]);
+        assert_eq!(
+            labels.into_array::<u8>().unwrap().into_raw_vec(),
+            vec![7, 2, 1]
+        );
+
+        let buf = [0, 0, 0x0b, 1, 0, 0, 0, 1, 0x01, 0x02];
+        let data = read_idx(&mut &buf[..]).unwrap();
+        assert_eq!(data.clone().into_array::<u8>(), None);
+        assert_eq!(data.into_array::<i16>().unwrap()[[0]], 0x0102);
+
+        assert!(read_idx(&mut &[0, 0, 0x0a, 0][..]).is_err());
+        assert!(read_idx(&mut &[1, 0, 8, 0][..]).is_err());
+
+        // 转置后的视图也按逻辑顺序写出
+        let a = ArrayD::from_shape_vec(IxDyn(&[2, 2]), vec![1u8, 2, 3, 4]).unwrap();
+        let t = a.t().to_owned();
+        let mut buf = vec![];
+        write_idx_array(&mut buf, &a.t().into_owned()).unwrap();
+        assert_eq!(&buf[12..], &[1, 3, 2, 4]);
+        assert_eq!(read_idx(&mut &buf[..]).unwrap(), IdxData::U8(t));
+    }
+}
@@ -1,50 +1,91 @@
-use std::io::Read;
+mod datasets;
+mod error;
+mod gz;
+mod idx;
+mod mmap;
 
-use anyhow::ensure;
-use bytes::Buf;
+use std::io::Read;
 
+pub use datasets::{transpose, Dataset, DatasetInfo, DatasetKind, LazyDataset};
+pub use error::IdxError;
+pub use gz::{decompress, open_idx};
+pub use idx::{
+    read_header, read_idx, read_idx_with, write_idx, write_idx_array, IdxData, IdxElem, IdxHeader,
+    IdxLimits,
+};
 pub use image;
 use image::{ImageBuffer, Luma};
+pub use mmap::{MmapDataset, MmapIdx};
+pub use ndarray;
+
+// gzip压缩的数据会自动解压
+pub fn parse_imgs_from_reader<R: Read>(
+    reader: &mut R,
+) -> Result<(u32, u32, Vec<Vec<u8>>), IdxError> {
+    parse_imgs_from_reader_with(reader, &IdxLimits::default())
+}
 
-pub fn parse_imgs_from_reader<R: Read>(reader: &mut R) -> anyhow::Result<(u32, u32, Vec<Vec<u8>>)> {
-    let mut data = vec![];
-    reader.read_to_end(&mut data).unwrap();
-    let mut buf = &data[..];
-
-    let magic = buf.get_i32();
-    ensure!(magic == 2051, "magic!=2051");
-    let img_cnt = buf.get_i32();
-    let rows = buf.get_u32();
-    let cols = buf.get_u32();
-    let mut rets = Vec::with_capacity(img_cnt as _);
-    for _ in 0..img_cnt {
-        let mut img: Vec<u8> = vec![0; (cols * rows) as _];
-        buf.read_exact(&mut img)?;
-        rets.push(img);
+// 图片文件的魔数是2051: u8类型, 3维 [图片数, 行, 列]
+pub fn parse_imgs_from_reader_with<R: Read>(
+    reader: &mut R,
+    limits: &IdxLimits,
+) -> Result<(u32, u32, Vec<Vec<u8>>), IdxError> {
+    let (header, body) = read_expected(reader, 2051, limits)?;
+    let (rows, cols) = (header.shape[1], header.shape[2]);
+    // 空图片不占数据, 图片个数不受IdxLimits限制, 直接拒绝
+    if rows == 0 || cols == 0 {
+        return Err(IdxError::EmptyImage { rows, cols });
     }
-    Ok((rows, cols, rets))
+    let imgs = body
+        .chunks_exact(rows * cols)
+        .map(|img| img.to_vec())
+        .collect();
+    Ok((rows as u32, cols as u32, imgs))
 }
 
-pub fn parse_labels_from_reader<R: Read>(reader: &mut R) -> anyhow::Result<Vec<u8>> {
-    let mut data = vec![];
-    reader.read_to_end(&mut data).unwrap();
-    let mut buf = &data[..];
-
-    let magic = buf.get_i32();
-    ensure!(magic == 2049, "magic!=2049");
-    let img_cnt = buf.get_i32();
-    let mut rets = vec![0; img_cnt as _];
-    buf.read_exact(&mut rets)?;
-    Ok(rets)
+pub fn parse_labels_from_reader<R: Read>(reader: &mut R) -> Result<Vec<u8>, IdxError> {
+    parse_labels_from_reader_with(reader, &IdxLimits::default())
 }
 
-pub fn to_img_buf(img: &[u8], rows: u32, cols: u32) -> ImageBuffer<Luma<u8>, Vec<u8>> {
-    let pxs = img;
-    let img = ImageBuffer::from_fn(cols as _, rows as _, |x, y| {
-        let v = pxs[(y * rows + x) as usize];
-        image::Luma([v])
-    });
-    img
+// 标签文件的魔数是2049: u8类型, 1维
+pub fn parse_labels_from_reader_with<R: Read>(
+    reader: &mut R,
+    limits: &IdxLimits,
+) -> Result<Vec<u8>, IdxError> {
+    Ok(read_expected(reader, 2049, limits)?.1)
+}
+
+fn read_expected<R: Read>(
+    reader: &mut R,
+    magic: u32,
+    limits: &IdxLimits,
+) -> Result<(IdxHeader, Vec<u8>), IdxError> {
+    let mut reader = decompress(reader)?;
+    let (header, size) = read_header(&mut reader, limits)?;
+    if header.magic() != magic {
+        return Err(IdxError::BadMagic {
+            expected: Some(magic),
+            actual: header.magic(),
+        });
+    }
+    let body = idx::read_body(&mut reader, &header, size)?;
+    Ok((header, body))
+}
+
+// img按行存储, 长度必须是 rows * cols
+pub fn to_img_buf(
+    img: &[u8],
+    rows: u32,
+    cols: u32,
+) -> Result<ImageBuffer<Luma<u8>, Vec<u8>>, IdxError> {
+    let expected = rows as usize * cols as usize;
+    if img.len() != expected {
+        return Err(IdxError::ImageSize {
+            expected,
+            actual: img.len(),
+        });
+    }
+    Ok(ImageBuffer::from_raw(cols, rows, img.to_vec()).unwrap())
 }
 
 #[cfg(test)]
@@ -52,6 +93,125 @@ mod tests {
 
     use super::*;
 
+    fn header(magic: u32, dims: &[u32]) -> Vec<u8> {
+        let mut buf = magic.to_be_bytes().to_vec();
+        for d in dims {
+            buf.extend_from_slice(&d.to_be_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn test_errors() {
+        // 比文件头还短
+        let r = parse_imgs_from_reader(&mut &[0, 0, 8, 3, 0, 0][..]);
+        assert!(matches!(
+            r,
+            Err(IdxError::Truncated {
+                expected: 16,
+                actual: 6
+            })
+        ));
+        let r = parse_imgs_from_reader(&mut &[0, 0, 8][..]);
+        assert!(matches!(
+            r,
+            Err(IdxError::Truncated {
+                expected: 4,
+                actual: 3
+            })
+        ));
+
+        // 标签文件当作图片文件读
+        let labels = [header(2049, &[2]), vec![1, 2]].concat();
+        assert!(matches!(
+            parse_imgs_from_reader(&mut &labels[..]),
+            Err(IdxError::BadMagic {
+                expected: Some(2051),
+                actual: 2049
+            })
+        ));
+        assert!(matches!(
+            read_idx(&mut &b"hello"[..]),
+            Err(IdxError::BadMagic { expected: None, .. })
+        ));
+        assert!(matches!(
+            read_idx(&mut &[0, 0, 0x0a, 0][..]),
+            Err(IdxError::UnknownDtype(0x0a))
+        ));
+
+        // 文件头声明了很大的维度, 不会提前分配内存
+        let huge = [header(2051, &[u32::MAX, 28, 28]), vec![0; 10]].concat();
+        assert!(matches!(
+            parse_imgs_from_reader(&mut &huge[..]),
+            Err(IdxError::TooLarge { .. })
+        ));
+        let r = parse_imgs_from_reader_with(&mut &huge[..], &IdxLimits::unlimited());
+        assert!(matches!(r, Err(IdxError::Truncated { actual: 26, .. })));
+        let overflow = header(0x0803, &[u32::MAX, u32::MAX, u32::MAX]);
+        assert!(matches!(
+            parse_imgs_from_reader_with(&mut &overflow[..], &IdxLimits::unlimited()),
+            Err(IdxError::DimensionOverflow)
+        ));
+
+        let imgs = [header(2051, &[2, 1, 2]), vec![1, 2, 3, 4]].concat();
+        let limits = IdxLimits { max_bytes: 3 };
+        assert!(matches!(
+            parse_imgs_from_reader_with(&mut &imgs[..], &limits),
+            Err(IdxError::TooLarge { size: 4, limit: 3 })
+        ));
+        let (rows, cols, imgs) = parse_imgs_from_reader(&mut &imgs[..]).unwrap();
+        assert_eq!((rows, cols), (1, 2));
+        assert_eq!(imgs, vec![vec![1, 2], vec![3, 4]]);
+
+        // 行数或列数为0时数据部分为空, 不会按声明的个数分配
+        let empty = header(2051, &[u32::MAX, 0, 28]);
+        assert!(matches!(
+            parse_imgs_from_reader(&mut &empty[..]),
+            Err(IdxError::EmptyImage { rows: 0, cols: 28 })
+        ));
+
+        // 像素个数和尺寸不一致
+        assert_eq!(
+            to_img_buf(&[1, 2, 3, 4], 2, 2).unwrap().get_pixel(1, 0).0,
+            [2]
+        );
+        assert!(matches!(
+            to_img_buf(&[1, 2, 3], 2, 2),
+            Err(IdxError::ImageSize {
+                expected: 4,
+                actual: 3
+            })
+        ));
+
+        // 图片和标签个数不一致
+        let dir = std::env::temp_dir().join(format!("mnist-loader-err-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let info = DatasetKind::Mnist.info();
+        let imgs = [header(2051, &[2, 1, 1]), vec![1, 2]].concat();
+        std::fs::write(dir.join(info.train_images), &imgs).unwrap();
+        std::fs::write(dir.join(info.test_images), &imgs).unwrap();
+        std::fs::write(dir.join(info.train_labels), &labels).unwrap();
+        std::fs::write(dir.join(info.test_labels), header(2049, &[1])).unwrap();
+        let err = Dataset::load(DatasetKind::Mnist, &dir).err().unwrap();
+        assert!(matches!(
+            err,
+            IdxError::InFile { ref error, .. } if matches!(**error, IdxError::Truncated { .. })
+        ));
+        std::fs::write(
+            dir.join(info.test_labels),
+            [header(2049, &[1]), vec![0]].concat(),
+        )
+        .unwrap();
+        assert!(matches!(
+            Dataset::load(DatasetKind::Mnist, &dir),
+            Err(IdxError::CountMismatch {
+                images: 2,
+                labels: 1
+            })
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
     #[test]
     fn it_works() {
         let mut train_imgs = std::fs::File::open("../data/train-images.idx3-ubyte").unwrap();
@@ -64,7 +224,7 @@ mod tests {
             let pxs = &imgs[i];
 
             let label = labels[i];
-            let img = to_img_buf(pxs, rows, cols);
+            let img = to_img_buf(pxs, rows, cols).unwrap();
             img.save(format!("../data/out-{}.png", label)).unwrap();
         }
     }
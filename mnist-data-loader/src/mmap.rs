@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::{gz::GZIP_MAGIC, read_header, IdxElem, IdxError, IdxLimits};
+
+/// 内存映射的idx文件, 按下标读取第一维的每一项, 不把整个文件读进内存
+/// 只支持没有压缩的文件
+pub struct MmapIdx {
+    mmap: Mmap,
+    dtype: u8,
+    shape: Vec<usize>,
+    // 数据开始的位置, 即文件头的长度
+    offset: usize,
+    // 每一项的字节数
+    item_len: usize,
+}
+
+impl MmapIdx {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, IdxError> {
+        let f = File::open(path)?;
+        // 映射期间文件不能被其他进程截断, 数据集文件一般是只读的
+        let mmap = unsafe { Mmap::map(&f)? };
+        if mmap.starts_with(&GZIP_MAGIC) {
+            return Err(IdxError::Gzip);
+        }
+        // 不会复制数据, 不需要限制大小
+        let (header, size) = read_header(&mut &mmap[..], &IdxLimits::unlimited())?;
+        let offset = header.byte_len();
+        if header.shape.is_empty() {
+            return Err(IdxError::BadMagic {
+                expected: None,
+                actual: header.magic(),
+            });
+        }
+        if ((mmap.len() - offset) as u64) < size {
+            return Err(IdxError::Truncated {
+                expected: offset as u64 + size,
+                actual: mmap.len() as u64,
+            });
+        }
+        let item_len = match header.shape[0] {
+            0 => 0,
+            n => (size / n as u64) as usize,
+        };
+        Ok(MmapIdx {
+            mmap,
+            dtype: header.dtype,
+            shape: header.shape,
+            offset,
+            item_len,
+        })
+    }
+
+    pub fn dtype(&self) -> u8 {
+        self.dtype
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    // 每一项的形状, 比如图片文件是 [行, 列]
+    pub fn item_shape(&self) -> &[usize] {
+        &self.shape[1..]
+    }
+
+    pub fn len(&self) -> usize {
+        self.shape[0]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 第i项的原始字节, 直接指向映射的内存, 多字节类型是大端的
+    pub fn get(&self, i: usize) -> Option<&[u8]> {
+        if i >= self.len() {
+            return None;
+        }
+        let start = self.offset + i * self.item_len;
+        Some(&self.mmap[start..start + self.item_len])
+    }
+
+    // 第i项转换成T, 会复制一次, u8类型可以直接用get
+    pub fn get_as<T: IdxElem>(&self, i: usize) -> Option<Vec<T>> {
+        if T::DTYPE != self.dtype {
+            return None;
+        }
+        Some(self.get(i)?.chunks_exact(T::SIZE).map(T::from_be).collect())
+    }
+}
+
+/// 内存映射的图片和标签文件, 按需读取样本, 适合内存放不下的大数据集
+/// EMNIST的图片是转置的, 需要时用 transpose 转回来
+pub struct MmapDataset {
+    images: MmapIdx,
+    labels: MmapIdx,
+}
+
+impl MmapDataset {
+    pub fn open(images: impl AsRef<Path>, labels: impl AsRef<Path>) -> Result<Self, IdxError> {
+        let images = MmapIdx::open(images)?;
+        let labels = MmapIdx::open(labels)?;
+        for (f, magic) in [(&images, 2051), (&labels, 2049)] {
+            let actual = u32::from_be_bytes([0, 0, f.dtype(), f.shape().len() as u8]);
+            if actual != magic {
+                return Err(IdxError::BadMagic {
+                    expected: Some(magic),
+                    actual,
+                });
+            }
+        }
+        if images.len() != labels.len() {
+            return Err(IdxError::CountMismatch {
+                images: images.len(),
+                labels: labels.len(),
+            });
+        }
+        Ok(MmapDataset { images, labels })
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.images.item_shape()[0] as u32
+    }
+
+    pub fn cols(&self) -> u32 {
+        self.images.item_shape()[1] as u32
+    }
+
+    // 第i张图片的像素, 按行存储, 不复制
+    pub fn image(&self, i: usize) -> &[u8] {
+        self.images.get(i).unwrap()
+    }
+
+    pub fn label(&self, i: usize) -> u8 {
+        self.labels.get(i).unwrap()[0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], u8)> + '_ {
+        (0..self.len()).map(|i| (self.image(i), self.label(i)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{ArrayD, IxDyn};
+
+    use super::{MmapDataset, MmapIdx};
+    use crate::{write_idx_array, IdxElem};
+
+    fn save<T: IdxElem>(path: &std::path::Path, arr: &ArrayD<T>) {
+        let mut buf = vec![];
+        write_idx_array(&mut buf, arr).unwrap();
+        std::fs::write(path, buf).unwrap();
+    }
+
+    #[test]
+    fn test() {
+        let dir = std::env::temp_dir().join(format!("mnist-loader-mmap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let images = ArrayD::from_shape_vec(IxDyn(&[3, 2, 2]), (0..12u8).collect()).unwrap();
+        let labels = ArrayD::from_shape_vec(IxDyn(&[3]), vec![5u8, 6, 7]).unwrap();
+        let features = ArrayD::from_shape_vec(IxDyn(&[2, 2]), vec![1.5f32, -2., 3., 4.]).unwrap();
+        save(&dir.join("images"), &images);
+        save(&dir.join("labels"), &labels);
+        save(&dir.join("features"), &features);
+
+        let ds = MmapDataset::open(dir.join("images"), dir.join("labels")).unwrap();
+        assert_eq!(ds.len(), 3);
+        assert_eq!((ds.rows(), ds.cols()), (2, 2));
+        assert_eq!(ds.image(1), &[4, 5, 6, 7]);
+        assert_eq!(ds.label(2), 7);
+        assert_eq!(ds.iter().map(|(_, l)| l).collect::<Vec<_>>(), vec![5, 6, 7]);
+
+        let f = MmapIdx::open(dir.join("features")).unwrap();
+        assert_eq!(f.item_shape(), &[2]);
+        assert_eq!(f.get_as::<f32>(1), Some(vec![3., 4.]));
+        assert_eq!(f.get_as::<u8>(1), None);
+        assert_eq!(f.get(2), None);
+
+        // 图片和标签个数不一致
+        assert!(MmapDataset::open(dir.join("images"), dir.join("features")).is_err());
+        // 文件被截断
+        let data = std::fs::read(dir.join("images")).unwrap();
+        std::fs::write(dir.join("short"), &data[..data.len() - 1]).unwrap();
+        assert!(MmapIdx::open(dir.join("short")).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
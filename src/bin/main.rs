@@ -1,47 +1,178 @@
-
-use hello_nn::loss_impls::{CrossEntropy};
-use hello_nn::util::{shuffle};
+use hello_nn::checkpoint::Checkpointer;
+use hello_nn::dataset::{
+    one_hot, split, Augmented, Augmenter, ClassMap, NormalizeKind, Normalized, Normalizer,
+    SplitConfig,
+};
+use hello_nn::history::History;
+use hello_nn::loss_impls::CrossEntropy;
+use hello_nn::metrics::{argmax, ConfusionMatrix};
+use hello_nn::tensorboard::SummaryWriter;
+use hello_nn::train::{FnSource, TrainConfig};
+use hello_nn::visualize::{
+    first_layer_filters, misclassified, plot_confusion, plot_history, save_model_histograms,
+    save_montage, ConfusionOptions, GalleryOptions, HistogramOptions, PlotOptions, TileOptions,
+};
 use hello_nn::{Mat, MatView, NeuralNetworkModel};
-use mnist_data_loader::{parse_imgs_from_reader, parse_labels_from_reader};
-
+use mnist_data_loader::{DatasetKind, LazyDataset};
 
 const BATCH_SIZE: usize = 16;
 const INPUT_SIZE: usize = 784;
 
-fn main() {
-    let mut model = NeuralNetworkModel::new();
-    model.push_dense_relu_layer(INPUT_SIZE, 256);
-    //model.push_dense_sigmod_layer(16, 16);
-    model.push_dense_softmax_layer(256, 10);
-    model.minimize(CrossEntropy::new());
-    //model.minimize(MSE::new());
-
-    let (mut data, mut labels) = load_train_data().unwrap();
-    let (test_data, test_labels) = load_test_data().unwrap();
-    let mut epoch = 0;
-    let _cnt = 20;
-    let mut loss = 999.;
+fn main() -> anyhow::Result<()> {
+    // 第一个参数是数据集名字, 比如 fashion-mnist, emnist-letters, 默认mnist
+    let kind = match std::env::args().nth(1) {
+        Some(name) => DatasetKind::from_name(&name)
+            .ok_or_else(|| anyhow::anyhow!("unknown dataset: {}", name))?,
+        None => DatasetKind::Mnist,
+    };
+    // 图片通过内存映射按需读取, 只有标签读进内存
+    let dataset = LazyDataset::open(kind, "data")?;
+
+    let mut model = NeuralNetworkModel::sequential(INPUT_SIZE)
+        .dense_relu(256)
+        //.dense_sigmod(16)
+        .dense_softmax(dataset.num_classes())
+        .minimize(CrossEntropy::new())
+        //.minimize(MSE::new())
+        .build()?;
+    model.summary();
+
+    // 从训练集中分出10%作为验证集用来选择模型, 测试集只用来报告选出的模型的结果
+    let parts = split(&dataset.train_labels, &SplitConfig::default())?;
+    let train_idx = &parts.train;
+    // 训练样本在每个批量里才转换成Mat, 不用事先复制整个训练集
+    let classes = dataset.num_classes();
+    let train = FnSource::new(train_idx.len(), |i: usize| {
+        let j = train_idx[i];
+        Ok((
+            to_mat(&dataset.train_image(j)),
+            one_hot(dataset.train_labels[j] as usize, classes),
+        ))
+    });
+    // 每轮对训练图片做轻微的随机平移和旋转
+    let augmenter = Augmenter::new().translate(2.)?.rotate(10.)?;
+    let (rows, cols) = (dataset.rows as usize, dataset.cols as usize);
+    let train = Augmented::new(train, augmenter, rows, cols, 0);
+    // 归一化参数只在训练集上计算, 保存下来推理时使用
+    let normalizer = Normalizer::fit(
+        NormalizeKind::GlobalStandardize,
+        train_idx.iter().map(|&j| to_mat(&dataset.train_image(j))),
+    )?;
+    normalizer.save("data/normalizer.json")?;
+    // 类别名也保存下来, 推理时把模型的输出转换回类别名
+    let class_map = ClassMap {
+        classes: dataset.info.classes.iter().map(|c| c.to_string()).collect(),
+    };
+    class_map.save("data/classes.json")?;
+    let val_data: Vec<Mat> = parts
+        .validation
+        .iter()
+        .map(|&j| normalizer.transform(&to_mat(&dataset.train_image(j))))
+        .collect::<Result<_, _>>()?;
+    let val_labels: Vec<Mat> = parts
+        .validation
+        .iter()
+        .map(|&j| one_hot(dataset.train_labels[j] as usize, classes))
+        .collect();
+    let test_images: Vec<_> = (0..dataset.test_labels.len())
+        .map(|i| dataset.test_image(i))
+        .collect();
+    let test_data: Vec<Mat> = test_images.iter().map(|img| to_mat(img)).collect();
+    let test_data = normalizer.transform_all(&test_data)?;
+    let mut train = Normalized::new(train, normalizer);
+    let test_labels = &dataset.test_labels;
+    let mut config = TrainConfig {
+        batch_size: BATCH_SIZE,
+        learning_rate: 0.1,
+        seed: Some(0),
+        ..Default::default()
+    };
+    // 有checkpoint时从上次中断的地方继续训练
+    let mut checkpointer = Checkpointer::new("data/checkpoints", 3)?;
+    let mut history = match checkpointer.resume()? {
+        Some(ckpt) => {
+            println!("resume from epoch {}", ckpt.epoch);
+            ckpt.restore(&mut model, &mut config)?
+        }
+        None => History::new(),
+    };
+    let mut writer = SummaryWriter::new("data/runs")?;
+    // 写几张训练图片, 方便在TensorBoard里确认数据没读错
+    for i in 0..dataset.train_labels.len().min(4) {
+        writer.add_image(
+            &format!("samples/{}", i),
+            &dataset.train_image(i),
+            dataset.rows,
+            dataset.cols,
+            0,
+        )?;
+    }
     loop {
-        let mut data_it = data.chunks(BATCH_SIZE);
-        let mut label_it = labels.chunks(BATCH_SIZE);
-        while let Some(data) = data_it.next() {
-            let label = label_it.next().unwrap();
-            loss = model.fit(data, label, 0.1);
+        let validation = Some((&val_data[..], &val_labels[..]));
+        let record = model.train_epoch_from(&mut train, validation, &config, &mut history)?;
+        println!(
+            "epoch: {}, loss: {}, val_loss: {}, val_accuracy: {}",
+            record.epoch + 1,
+            record.loss,
+            record.val_loss.unwrap_or(f64::NAN),
+            record.metrics["val_accuracy"]
+        );
+        writer.add_epoch(&record)?;
+        writer.add_model_histograms(&model, record.epoch as i64)?;
+        writer.flush()?;
+        let best = checkpointer.best;
+        checkpointer.on_epoch(&model, &config, &history)?;
+        // 验证集上的结果变好时才看测试集
+        if checkpointer.best != best {
+            print_rate(&mut model, &test_data, test_labels)?;
+            // 最自信的100个错误拼成一张图
+            let labels: Vec<usize> = test_labels.iter().map(|l| *l as usize).collect();
+            let errors = misclassified(&mut model, &test_data, &labels)?;
+            let options = GalleryOptions {
+                class_names: class_map.classes.clone(),
+                ..Default::default()
+            };
+            let errors = &errors[..errors.len().min(100)];
+            save_montage(
+                "data/errors.png",
+                errors,
+                &test_images,
+                dataset.rows,
+                dataset.cols,
+                &options,
+            )?;
+            // 第一层每个神经元的权重模板和各层参数的分布
+            first_layer_filters(&model, dataset.rows, dataset.cols, &TileOptions::default())?
+                .save("data/filters.png")?;
+            save_model_histograms("data/weights.png", &model, &HistogramOptions::default())?;
+            // 测试集上的混淆矩阵
+            let predicted = test_data
+                .iter()
+                .map(|data| Ok(argmax(&model.predict(&data.view())?.view())))
+                .collect::<hello_nn::Result<Vec<_>>>()?;
+            let matrix = ConfusionMatrix::from_labels(&labels, &predicted, dataset.num_classes());
+            let options = ConfusionOptions {
+                class_names: options.class_names,
+                ..Default::default()
+            };
+            plot_confusion(&matrix, &options).save("data/confusion.png")?;
         }
-        epoch += 1;
-        //cnt -= 1;
-        println!("epoch: {}, loss: {}", epoch, loss);
-        print_rate(&mut model, &test_data, &test_labels);
-        (data, labels) = shuffle(data, labels);
+        plot_history(&history, &PlotOptions::default()).save("data/history.png")?;
+        history.save_epochs_csv("data/history.csv")?;
+        history.save_json("data/history.json")?;
     }
 }
 
-pub fn print_rate(model: &mut NeuralNetworkModel, datas: &[Mat], labels: &[u8]) {
+pub fn print_rate(
+    model: &mut NeuralNetworkModel,
+    datas: &[Mat],
+    labels: &[u8],
+) -> anyhow::Result<()> {
     let mut accept = 0;
     let mut wrong = 0;
 
     for (i, data) in datas.iter().enumerate() {
-        let r = model.predict(&data.view());
+        let r = model.predict(&data.view())?;
 
         let got = judge(&r.view());
         let want = labels[i];
@@ -55,83 +186,15 @@ pub fn print_rate(model: &mut NeuralNetworkModel, datas: &[Mat], labels: &[u8])
     }
     let rate = accept as f32 / (accept + wrong) as f32 * 100.;
     println!("accept: {}, wrong:{}, rate: {:.2}%", accept, wrong, rate);
+    Ok(())
 }
 
-pub fn load_train_data() -> anyhow::Result<(Vec<Mat>, Vec<Mat>)> {
-    let mut train_imgs = std::fs::File::open("data/train-images.idx3-ubyte").unwrap();
-    let mut train_labels = std::fs::File::open("data/train-labels.idx1-ubyte").unwrap();
-
-    let (row, col, imgs) = parse_imgs_from_reader(&mut train_imgs).unwrap();
-    let labels = parse_labels_from_reader(&mut train_labels).unwrap();
-
-    let mut r_datas = vec![];
-    let mut r_labels = vec![];
-
-    let num_px = row * col;
-
-    for data in imgs {
-        // 灰度值转换为 0 - 1 的小数
-        let data = data
-            .into_iter()
-            .map(|v| v as f32 / u8::MAX as f32)
-            .collect();
-        let d = Mat::from_shape_vec((num_px as _, 1), data)?;
-        r_datas.push(d);
-    }
-
-    for label in labels {
-        let mut l = vec![];
-        for i in 0..10 {
-            if i == label as i32 {
-                l.push(1.);
-            } else {
-                l.push(0.);
-            }
-        }
-        let l = Mat::from_shape_vec((10, 1), l)?;
-        r_labels.push(l);
-    }
-
-    Ok((r_datas, r_labels))
-}
-
-pub fn load_test_data() -> anyhow::Result<(Vec<Mat>, Vec<u8>)> {
-    let mut test_imgs = std::fs::File::open("data/t10k-images.idx3-ubyte").unwrap();
-    let mut test_labels = std::fs::File::open("data/t10k-labels.idx1-ubyte").unwrap();
-
-    let (row, col, imgs) = parse_imgs_from_reader(&mut test_imgs).unwrap();
-    let labels = parse_labels_from_reader(&mut test_labels).unwrap();
-
-    let mut r_datas = vec![];
-    let mut r_labels = vec![];
-
-    let num_px = row * col;
-
-    for data in imgs {
-        // 灰度值转换为 0 - 1 的小数
-        let data = data
-            .into_iter()
-            .map(|v| v as f32 / u8::MAX as f32)
-            .collect();
-        let d = Mat::from_shape_vec((num_px as _, 1), data)?;
-        r_datas.push(d);
-    }
-
-    for label in labels {
-        r_labels.push(label);
-    }
-
-    Ok((r_datas, r_labels))
+// 灰度值转换为Mat, 缩放由Normalizer完成
+pub fn to_mat(img: &[u8]) -> Mat {
+    let data = img.iter().map(|v| *v as f32).collect();
+    Mat::from_shape_vec((INPUT_SIZE, 1), data).unwrap()
 }
 
 pub fn judge(result: &MatView) -> u8 {
-    let mut max: f32 = -999999.;
-    let mut idx = 0;
-    for (i, v) in result.iter().enumerate() {
-        if *v > max {
-            max = *v;
-            idx = i as _;
-        }
-    }
-    idx
+    argmax(result) as u8
 }
@@ -0,0 +1,83 @@
+use hello_nn::dataset::{
+    balanced_subset, one_hot, select, split, NormalizeKind, Normalizer, SplitConfig,
+};
+use hello_nn::loss_impls::CrossEntropy;
+use hello_nn::search::{Params, Search, SearchSpace, Strategy};
+use hello_nn::train::TrainConfig;
+use hello_nn::{Mat, NeuralNetworkModel};
+use mnist_data_loader::{DatasetKind, LazyDataset};
+
+// 在MNIST的一个小子集上搜索学习率, 批量大小和隐藏层大小
+// 记录保存在 data/search.json, 中断后重新运行会跳过已成功的试验, 出错的试验重新运行
+fn main() -> anyhow::Result<()> {
+    // 只用到一个小子集, 图片按需从内存映射的文件中读取
+    let dataset = LazyDataset::open(DatasetKind::Mnist, "data")?;
+    let classes = dataset.num_classes();
+    let subset = balanced_subset(&dataset.train_labels, 2000, 0);
+    let labels: Vec<u8> = subset.iter().map(|&i| dataset.train_labels[i]).collect();
+    let datas: Vec<Mat> = subset
+        .iter()
+        .map(|&i| to_mat(&dataset.train_image(i)))
+        .collect();
+    let targets: Vec<Mat> = labels
+        .iter()
+        .map(|l| one_hot(*l as usize, classes))
+        .collect();
+
+    let parts = split(&labels, &SplitConfig::default())?;
+    let normalizer = Normalizer::fit(
+        NormalizeKind::GlobalStandardize,
+        parts.train.iter().map(|&i| &datas[i]),
+    )?;
+    let datas = normalizer.transform_all(&datas)?;
+    let (train_data, train_labels) = (select(&datas, &parts.train), select(&targets, &parts.train));
+    let (val_data, val_labels) = (
+        select(&datas, &parts.validation),
+        select(&targets, &parts.validation),
+    );
+
+    let space = SearchSpace::new()
+        .log_uniform("learning_rate", 0.01, 0.5)
+        .choice("batch_size", &[8., 16., 32., 64.])
+        .choice("hidden", &[64., 128., 256.]);
+    let strategy = Strategy::SuccessiveHalving {
+        trials: 27,
+        min_budget: 1,
+        eta: 3,
+    };
+    let mut search = Search::new(space, strategy, 9);
+    search.log_path = Some("data/search.json".into());
+    search.verbose = true;
+
+    let result = search.run(|params: &Params, budget| {
+        let mut model: NeuralNetworkModel = NeuralNetworkModel::sequential(datas[0].len())
+            .dense_relu(params.get_usize("hidden")?)
+            .dense_softmax(classes)
+            .minimize(CrossEntropy::new())
+            .build()?;
+        let config = TrainConfig {
+            epochs: budget,
+            batch_size: params.get_usize("batch_size")?,
+            learning_rate: params.get("learning_rate")? as f32,
+            seed: Some(0),
+            ..Default::default()
+        };
+        let history = model.train(
+            &train_data,
+            &train_labels,
+            Some((&val_data, &val_labels)),
+            &config,
+        )?;
+        Ok(history.last().unwrap().metrics["val_accuracy"])
+    })?;
+    match result.best {
+        Some(best) => println!("best: {:?}, score: {:?}", best.params.0, best.score),
+        None => println!("all trials failed"),
+    }
+    Ok(())
+}
+
+fn to_mat(img: &[u8]) -> Mat {
+    let data = img.iter().map(|v| *v as f32).collect();
+    Mat::from_shape_vec((img.len(), 1), data).unwrap()
+}
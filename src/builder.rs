@@ -0,0 +1,178 @@
+use crate::{
+    layer_impls::{ReLULayer, SigmodLayer, SoftmaxLayer},
+    Error, Float, Layer, Loss, NeuralNetworkModel, Reduction, Result,
+};
+
+// 待构建的层, 全连接层的输入个数要等到build时才能推断出来
+enum LayerSpec<T: Float> {
+    Dense(usize),
+    Layer(Box<dyn Layer<T>>),
+}
+
+/// 顺序模型构建器, 只需要声明一次输入个数, 之后每层只声明输出个数
+/// 每层的输入个数由前一层的输出推断, 结构不一致时build返回错误
+pub struct SequentialBuilder<T: Float = f32> {
+    input_size: usize,
+    layers: Vec<LayerSpec<T>>,
+    loss: Option<Box<dyn Loss<T>>>,
+    reduction: Reduction,
+}
+
+impl<T: Float> SequentialBuilder<T> {
+    pub fn new(input_size: usize) -> Self {
+        SequentialBuilder {
+            input_size,
+            layers: vec![],
+            loss: None,
+            reduction: Reduction::Mean,
+        }
+    }
+
+    // 没有激活函数的全连接层, cell_cnt是本层神经元个数
+    pub fn dense(mut self, cell_cnt: usize) -> Self {
+        self.layers.push(LayerSpec::Dense(cell_cnt));
+        self
+    }
+    pub fn relu(self) -> Self {
+        self.layer(ReLULayer::new())
+    }
+    pub fn sigmod(self) -> Self {
+        self.layer(SigmodLayer::new())
+    }
+    pub fn softmax(self) -> Self {
+        self.layer(SoftmaxLayer::new())
+    }
+    pub fn dense_relu(self, cell_cnt: usize) -> Self {
+        self.dense(cell_cnt).relu()
+    }
+    pub fn dense_sigmod(self, cell_cnt: usize) -> Self {
+        self.dense(cell_cnt).sigmod()
+    }
+    pub fn dense_softmax(self, cell_cnt: usize) -> Self {
+        self.dense(cell_cnt).softmax()
+    }
+    // 添加任意已经构建好的层, build时检查输入个数是否和前一层输出一致
+    pub fn layer<L: Layer<T> + 'static>(mut self, layer: L) -> Self {
+        self.layers.push(LayerSpec::Layer(Box::new(layer)));
+        self
+    }
+
+    pub fn minimize(mut self, loss: impl Loss<T> + 'static) -> Self {
+        self.loss = Some(Box::new(loss));
+        self
+    }
+    pub fn reduction(mut self, reduction: Reduction) -> Self {
+        self.reduction = reduction;
+        self
+    }
+
+    pub fn build(self) -> Result<NeuralNetworkModel<T>> {
+        if self.input_size == 0 {
+            return Err(Error::InvalidArchitecture(
+                "input size must be greater than 0".into(),
+            ));
+        }
+        if self.layers.is_empty() {
+            return Err(Error::InvalidArchitecture("model has no layers".into()));
+        }
+
+        let mut model = NeuralNetworkModel::new();
+        model.input_size = Some(self.input_size);
+        model.loss = self.loss;
+        model.reduction = self.reduction;
+
+        // 前一层的输出个数
+        let mut size = self.input_size;
+        for (i, spec) in self.layers.into_iter().enumerate() {
+            match spec {
+                LayerSpec::Dense(cell_cnt) => {
+                    if cell_cnt == 0 {
+                        return Err(Error::InvalidArchitecture(format!(
+                            "layer {}: dense layer has 0 cells",
+                            i
+                        )));
+                    }
+                    model.push_dense_layer(size, cell_cnt);
+                    size = cell_cnt;
+                }
+                LayerSpec::Layer(layer) => {
+                    if let Some(want) = layer.input_size() {
+                        if want != size {
+                            return Err(Error::ShapeMismatch {
+                                layer: i,
+                                expected: (want, 1),
+                                actual: (size, 1),
+                            });
+                        }
+                    }
+                    size = layer.output_size(size);
+                    model.layers.push(layer);
+                }
+            }
+        }
+        Ok(model)
+    }
+}
+
+impl<T: Float> NeuralNetworkModel<T> {
+    // 从输入个数开始构建一个顺序模型
+    pub fn sequential(input_size: usize) -> SequentialBuilder<T> {
+        SequentialBuilder::new(input_size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{layer_impls::DenseLayerNoActive, Error, NeuralNetworkModel};
+
+    #[test]
+    fn test() {
+        let model: NeuralNetworkModel = NeuralNetworkModel::sequential(4)
+            .dense_relu(3)
+            .dense_sigmod(2)
+            .build()
+            .unwrap();
+        assert_eq!(model.input_size, Some(4));
+        let sizes = model.layers.iter().fold(vec![4], |mut acc, l| {
+            acc.push(l.output_size(*acc.last().unwrap()));
+            acc
+        });
+        assert_eq!(sizes, vec![4, 3, 3, 2, 2]);
+
+        // 自定义层的输入个数和前一层匹配
+        let model: NeuralNetworkModel = NeuralNetworkModel::sequential(4)
+            .dense(3)
+            .layer(DenseLayerNoActive::new(3, 5))
+            .softmax()
+            .build()
+            .unwrap();
+        assert_eq!(model.layers.len(), 3);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = NeuralNetworkModel::<f32>::sequential(4)
+            .dense_relu(3)
+            .layer(DenseLayerNoActive::new(4, 2))
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            Error::ShapeMismatch {
+                layer: 2,
+                expected: (4, 1),
+                actual: (3, 1)
+            }
+        );
+        assert!(NeuralNetworkModel::<f32>::sequential(0)
+            .dense(1)
+            .build()
+            .is_err());
+        assert!(NeuralNetworkModel::<f32>::sequential(4).build().is_err());
+        assert!(NeuralNetworkModel::<f64>::sequential(4)
+            .dense(0)
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,406 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::write_atomic;
+use crate::{history::History, train::TrainConfig, Error, Float, Mat, NeuralNetworkModel, Result};
+
+/// 一个参数矩阵, 数值统一保存为f64, f32和f64的模型可以互相加载
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParamState {
+    pub name: String,
+    pub shape: (usize, usize),
+    pub data: Vec<f64>,
+}
+
+/// 一层的参数, 没有参数的层params为空
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerState {
+    pub name: String,
+    pub params: Vec<ParamState>,
+}
+
+/// 训练参数, 和TrainConfig一一对应, 学习率保存为f64
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigState {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f64,
+    pub shuffle: bool,
+    // 打乱样本用的随机种子, 每轮的随机状态由 种子+轮次 决定, 只保存种子就能精确恢复
+    pub seed: Option<u64>,
+}
+
+impl ConfigState {
+    pub fn new<T: Float>(config: &TrainConfig<T>) -> Self {
+        ConfigState {
+            epochs: config.epochs,
+            batch_size: config.batch_size,
+            learning_rate: config.learning_rate.to_f64().unwrap(),
+            shuffle: config.shuffle,
+            seed: config.seed,
+        }
+    }
+
+    pub fn to_config<T: Float>(&self) -> TrainConfig<T> {
+        TrainConfig {
+            epochs: self.epochs,
+            batch_size: self.batch_size,
+            learning_rate: T::of(self.learning_rate),
+            shuffle: self.shuffle,
+            seed: self.seed,
+        }
+    }
+}
+
+/// 训练状态的快照, 恢复后可以从中断的地方继续训练
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    // 已完成的轮数, 恢复后从这一轮继续
+    pub epoch: usize,
+    // 已完成的批量个数
+    pub step: usize,
+    pub layers: Vec<LayerState>,
+    // 完整的训练参数, 批量大小和是否打乱也决定了恢复后每个批量的样本
+    // SGD的优化器状态只有其中的学习率
+    pub config: ConfigState,
+    // 目前为止最好的指标值
+    pub best_metric: Option<f64>,
+    // 训练历史, 学习率调整也按它记录的轮次继续
+    pub history: History,
+}
+
+impl Checkpoint {
+    pub fn new<T: Float>(
+        model: &NeuralNetworkModel<T>,
+        config: &TrainConfig<T>,
+        history: &History,
+        best_metric: Option<f64>,
+    ) -> Self {
+        let layers = model
+            .layers
+            .iter()
+            .map(|layer| LayerState {
+                name: layer.name().to_string(),
+                params: layer
+                    .params()
+                    .into_iter()
+                    .map(|(name, p)| ParamState {
+                        name: name.to_string(),
+                        shape: p.dim(),
+                        data: p.iter().map(|v| v.to_f64().unwrap()).collect(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Checkpoint {
+            epoch: history.epochs.len(),
+            step: history.steps(),
+            layers,
+            config: ConfigState::new(config),
+            best_metric,
+            history: history.clone(),
+        }
+    }
+
+    // 把参数写回模型, 恢复训练参数, 返回之前的训练历史
+    // 模型结构对不上或数据不完整时返回错误, 不会修改模型和config
+    pub fn restore<T: Float>(
+        &self,
+        model: &mut NeuralNetworkModel<T>,
+        config: &mut TrainConfig<T>,
+    ) -> Result<History> {
+        if model.layers.len() != self.layers.len() {
+            return Err(Error::CheckpointMismatch(format!(
+                "model has {} layers, checkpoint has {}",
+                model.layers.len(),
+                self.layers.len()
+            )));
+        }
+        for (i, (layer, state)) in model.layers.iter().zip(&self.layers).enumerate() {
+            let params = layer.params();
+            let same = layer.name() == state.name
+                && params.len() == state.params.len()
+                && params.iter().zip(&state.params).all(|((name, p), s)| {
+                    *name == s.name && p.dim() == s.shape && s.data.len() == s.shape.0 * s.shape.1
+                });
+            if !same {
+                return Err(Error::CheckpointMismatch(format!(
+                    "layer {} ({}) does not match {}",
+                    i,
+                    layer.name(),
+                    state.name
+                )));
+            }
+        }
+
+        // 上面已经检查过形状和数据长度, 这里不会失败
+        for (layer, state) in model.layers.iter_mut().zip(&self.layers) {
+            for ((_, p), s) in layer.params_mut().into_iter().zip(&state.params) {
+                let data = s.data.iter().map(|v| T::of(*v)).collect();
+                *p = Mat::from_shape_vec(s.shape, data).unwrap();
+            }
+        }
+        *config = self.config.to_config();
+        Ok(self.history.clone())
+    }
+
+    // 先写临时文件并同步到磁盘再改名, 写到一半被杀掉或断电也不会破坏已有的文件
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        write_atomic(path.as_ref(), serde_json::to_string(self)?.as_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&s)?)
+    }
+}
+
+/// 训练时定期保存checkpoint到目录中, 只保留最近keep个
+/// 文件名为 ckpt-<轮次>.json, 监控的指标变好时另外保存为 best.json
+pub struct Checkpointer {
+    dir: PathBuf,
+    // 保留最近的几个checkpoint
+    pub keep: usize,
+    // 每隔几轮保存一次
+    pub every: usize,
+    // 挑选最好模型的指标, 名字里有loss时越小越好, 否则越大越好
+    pub monitor: String,
+    pub best: Option<f64>,
+}
+
+impl Checkpointer {
+    // keep至少为1, 否则刚保存的checkpoint会马上被删除
+    pub fn new(dir: impl AsRef<Path>, keep: usize) -> io::Result<Self> {
+        if keep == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Checkpointer must keep at least one checkpoint",
+            ));
+        }
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Checkpointer {
+            dir: dir.as_ref().to_path_buf(),
+            keep,
+            every: 1,
+            monitor: "val_loss".to_string(),
+            best: None,
+        })
+    }
+
+    // 每轮训练结束后调用, 返回本轮保存的checkpoint路径
+    // 打乱样本时必须设置config.seed, 否则恢复后不能和不中断的训练完全一致
+    pub fn on_epoch<T: Float>(
+        &mut self,
+        model: &NeuralNetworkModel<T>,
+        config: &TrainConfig<T>,
+        history: &History,
+    ) -> io::Result<Option<PathBuf>> {
+        if config.shuffle && config.seed.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "checkpointing a shuffled run requires TrainConfig::seed",
+            ));
+        }
+        let value = history.metric(&self.monitor).last().copied().flatten();
+        let improved = match (value, self.best) {
+            (Some(v), Some(best)) if self.monitor.contains("loss") => v < best,
+            (Some(v), Some(best)) => v > best,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if improved {
+            self.best = value;
+            Checkpoint::new(model, config, history, self.best).save(self.dir.join("best.json"))?;
+        }
+
+        let epoch = history.epochs.len();
+        if self.every == 0 || epoch == 0 || !epoch.is_multiple_of(self.every) {
+            return Ok(None);
+        }
+        let path = self.dir.join(format!("ckpt-{:06}.json", epoch));
+        Checkpoint::new(model, config, history, self.best).save(&path)?;
+
+        let list = self.list()?;
+        if list.len() > self.keep {
+            for old in &list[..list.len() - self.keep] {
+                std::fs::remove_file(old)?;
+            }
+        }
+        Ok(Some(path))
+    }
+
+    // 目录中所有的checkpoint, 从旧到新
+    pub fn list(&self) -> io::Result<Vec<PathBuf>> {
+        let mut list = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if name.starts_with("ckpt-") && name.ends_with(".json") {
+                list.push(path);
+            }
+        }
+        list.sort();
+        Ok(list)
+    }
+
+    // 读取最近的checkpoint, 同时恢复最好的指标值, 没有时返回None
+    pub fn resume(&mut self) -> io::Result<Option<Checkpoint>> {
+        let Some(path) = self.list()?.pop() else {
+            return Ok(None);
+        };
+        let ckpt = Checkpoint::load(path)?;
+        self.best = ckpt.best_metric;
+        Ok(Some(ckpt))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{
+        history::History, loss_impls::CrossEntropy, train::TrainConfig, Error, Mat,
+        NeuralNetworkModel,
+    };
+
+    use super::{Checkpoint, Checkpointer};
+
+    fn new_model() -> NeuralNetworkModel {
+        NeuralNetworkModel::sequential(2)
+            .dense_softmax(2)
+            .minimize(CrossEntropy::new())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test() {
+        let datas: Vec<Mat> = vec![
+            array![[1.], [0.]],
+            array![[0.], [1.]],
+            array![[0.9], [0.1]],
+            array![[0.2], [0.8]],
+            array![[0.7], [0.4]],
+        ];
+        let labels: Vec<Mat> = vec![
+            array![[1.], [0.]],
+            array![[0.], [1.]],
+            array![[1.], [0.]],
+            array![[0.], [1.]],
+            array![[1.], [0.]],
+        ];
+        let config = TrainConfig {
+            batch_size: 2,
+            learning_rate: 0.5,
+            seed: Some(7),
+            ..Default::default()
+        };
+        let validation = Some((&datas[..], &labels[..]));
+
+        // 不中断训练4轮作为对照, 两个模型的初始参数相同
+        let mut model = new_model();
+        let mut interrupted = new_model();
+        Checkpoint::new(&model, &config, &History::new(), None)
+            .restore(&mut interrupted, &mut config.clone())
+            .unwrap();
+        let mut history = History::new();
+        for _ in 0..4 {
+            model
+                .train_epoch(&datas, &labels, validation, &config, &mut history)
+                .unwrap();
+        }
+
+        // 训练2轮后中断, 用新模型恢复再训练2轮
+        let dir = std::env::temp_dir().join(format!("hello-nn-ckpt-{}", std::process::id()));
+        let mut ckpt = Checkpointer::new(&dir, 2).unwrap();
+        let mut h = History::new();
+        for _ in 0..3 {
+            interrupted
+                .train_epoch(&datas, &labels, validation, &config, &mut h)
+                .unwrap();
+            ckpt.on_epoch(&interrupted, &config, &h).unwrap();
+        }
+        let list = ckpt.list().unwrap();
+        assert_eq!(list.len(), 2);
+        assert!(list[0].ends_with("ckpt-000002.json"));
+        assert!(dir.join("best.json").exists());
+        std::fs::remove_file(&list[1]).unwrap();
+
+        let mut ckpt = Checkpointer::new(&dir, 2).unwrap();
+        let state = ckpt.resume().unwrap().unwrap();
+        assert_eq!(state.epoch, 2);
+        assert_eq!(state.step, 6);
+        assert_eq!(
+            ckpt.best,
+            h.epochs[..2]
+                .iter()
+                .filter_map(|e| e.val_loss)
+                .reduce(f64::min)
+        );
+
+        let mut resumed = new_model();
+        let mut config2 = TrainConfig::default();
+        let mut h = state.restore(&mut resumed, &mut config2).unwrap();
+        assert_eq!(config2.learning_rate, 0.5);
+        assert_eq!(config2.seed, Some(7));
+        assert_eq!(config2.batch_size, 2);
+        for _ in 0..2 {
+            resumed
+                .train_epoch(&datas, &labels, validation, &config2, &mut h)
+                .unwrap();
+        }
+        assert_eq!(h.epochs.len(), 4);
+        assert_eq!(h.steps(), 12);
+        for (a, b) in resumed.layers[0]
+            .params()
+            .iter()
+            .zip(model.layers[0].params())
+        {
+            assert_eq!(a.1, b.1);
+        }
+
+        // 结构不同的模型不能恢复
+        let mut other: NeuralNetworkModel = NeuralNetworkModel::sequential(2)
+            .dense_softmax(3)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            state.restore(&mut other, &mut config2),
+            Err(Error::CheckpointMismatch(_))
+        ));
+
+        // 数据被截断时在修改之前报错, 模型保持原样
+        let mut truncated = state.clone();
+        truncated.layers[0].params[1].data.pop();
+        let mut fresh = new_model();
+        let before: Vec<Mat> = fresh.layers[0]
+            .params()
+            .into_iter()
+            .map(|(_, p)| p.clone())
+            .collect();
+        assert!(matches!(
+            truncated.restore(&mut fresh, &mut TrainConfig::default()),
+            Err(Error::CheckpointMismatch(_))
+        ));
+        let after: Vec<Mat> = fresh.layers[0]
+            .params()
+            .into_iter()
+            .map(|(_, p)| p.clone())
+            .collect();
+        assert_eq!(before, after);
+
+        assert!(Checkpointer::new(&dir, 0).is_err());
+
+        // 没有种子时无法精确恢复
+        let config = TrainConfig {
+            seed: None,
+            ..config
+        };
+        let err = ckpt.on_epoch(&model, &config, &history).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dataset::{k_fold, select},
+    history::History,
+    metrics::argmax,
+    train::TrainConfig,
+    Error, Float, Mat, NeuralNetworkModel, Result,
+};
+
+/// 交叉验证参数
+#[derive(Debug, Clone)]
+pub struct CrossValidation {
+    // 折数, 至少为2
+    pub k: usize,
+    // 按类别分层, 类别取期望结果中最大值的下标
+    pub stratify: bool,
+    pub seed: u64,
+    // 要统计的指标, 取每折最后一轮的值, 名字和History::metric一样
+    pub metrics: Vec<String>,
+}
+
+impl Default for CrossValidation {
+    fn default() -> Self {
+        CrossValidation {
+            k: 5,
+            stratify: true,
+            seed: 0,
+            metrics: vec!["val_loss".to_string(), "val_accuracy".to_string()],
+        }
+    }
+}
+
+/// 一折的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FoldResult {
+    pub fold: usize,
+    pub metrics: BTreeMap<String, f64>,
+    pub history: History,
+}
+
+/// 所有折的结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CrossValidationReport {
+    pub folds: Vec<FoldResult>,
+}
+
+impl CrossValidationReport {
+    // 某个指标在各折上的值, 有的折没有这个指标时跳过
+    pub fn values(&self, name: &str) -> Vec<f64> {
+        self.folds
+            .iter()
+            .filter_map(|f| f.metrics.get(name).copied())
+            .collect()
+    }
+
+    pub fn mean(&self, name: &str) -> Option<f64> {
+        let v = self.values(name);
+        if v.is_empty() {
+            return None;
+        }
+        Some(v.iter().sum::<f64>() / v.len() as f64)
+    }
+
+    // 样本标准差 (除以 n-1), 只有一折时为0
+    pub fn std(&self, name: &str) -> Option<f64> {
+        let v = self.values(name);
+        let mean = self.mean(name)?;
+        if v.len() < 2 {
+            return Some(0.);
+        }
+        let var = v.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (v.len() - 1) as f64;
+        Some(var.sqrt())
+    }
+
+    // 每个指标的 (均值, 标准差)
+    pub fn summary(&self) -> BTreeMap<String, (f64, f64)> {
+        let mut names: Vec<&String> = self.folds.iter().flat_map(|f| f.metrics.keys()).collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .map(|n| (n.clone(), (self.mean(n).unwrap(), self.std(n).unwrap())))
+            .collect()
+    }
+}
+
+impl fmt::Display for CrossValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}-fold cross validation", self.folds.len())?;
+        for (name, (mean, std)) in self.summary() {
+            writeln!(f, "{}: {:.4} ± {:.4}", name, mean, std)?;
+        }
+        Ok(())
+    }
+}
+
+// K折交叉验证, 每折用factory构建一个新模型, 用train训练config.epochs轮
+// 验证集就是当前这一折, 指标由训练循环计算, 和正常训练时的 val_loss / val_accuracy 一致
+// factory的参数是第几折, k小于2或大于样本数, 样本和期望结果个数不一致时返回Err
+pub fn cross_validate<T: Float>(
+    mut factory: impl FnMut(usize) -> Result<NeuralNetworkModel<T>>,
+    datas: &[Mat<T>],
+    labels: &[Mat<T>],
+    config: &TrainConfig<T>,
+    cv: &CrossValidation,
+) -> Result<CrossValidationReport> {
+    if datas.len() != labels.len() {
+        return Err(Error::BatchSizeMismatch {
+            expected: datas.len(),
+            actual: labels.len(),
+        });
+    }
+    let classes: Vec<usize> = labels.iter().map(|l| argmax(&l.view())).collect();
+    // 先划分好所有折, 划分不出来时不会构建任何模型
+    let splits = k_fold(&classes, cv.k, cv.stratify, cv.seed)?;
+    let mut report = CrossValidationReport::default();
+    for (fold, split) in splits.into_iter().enumerate() {
+        let mut model = factory(fold)?;
+        let train_datas = select(datas, &split.train);
+        let train_labels = select(labels, &split.train);
+        let val_datas = select(datas, &split.validation);
+        let val_labels = select(labels, &split.validation);
+        let history = model.train(
+            &train_datas,
+            &train_labels,
+            Some((&val_datas, &val_labels)),
+            config,
+        )?;
+        let metrics = cv
+            .metrics
+            .iter()
+            .filter_map(|name| {
+                let v = history.metric(name).last().copied().flatten()?;
+                Some((name.clone(), v))
+            })
+            .collect();
+        report.folds.push(FoldResult {
+            fold,
+            metrics,
+            history,
+        });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{loss_impls::CrossEntropy, train::TrainConfig, Error, Mat, NeuralNetworkModel};
+
+    use super::{cross_validate, CrossValidation};
+
+    #[test]
+    fn test() {
+        // 两类, 第一个特征大的是类别0
+        let datas: Vec<Mat> = (0..12)
+            .map(|i| {
+                let x = (i % 6) as f32 / 10.;
+                if i < 6 {
+                    array![[0.9 - x], [x]]
+                } else {
+                    array![[x], [0.9 - x]]
+                }
+            })
+            .collect();
+        let labels: Vec<Mat> = (0..12)
+            .map(|i| {
+                if i < 6 {
+                    array![[1.], [0.]]
+                } else {
+                    array![[0.], [1.]]
+                }
+            })
+            .collect();
+        let config = TrainConfig {
+            epochs: 20,
+            batch_size: 4,
+            learning_rate: 0.5,
+            seed: Some(0),
+            ..Default::default()
+        };
+        let cv = CrossValidation {
+            k: 3,
+            ..Default::default()
+        };
+        let mut built = vec![];
+        let report = cross_validate(
+            |fold| {
+                built.push(fold);
+                NeuralNetworkModel::sequential(2)
+                    .dense_softmax(2)
+                    .minimize(CrossEntropy::new())
+                    .build()
+            },
+            &datas,
+            &labels,
+            &config,
+            &cv,
+        )
+        .unwrap();
+        assert_eq!(built, vec![0, 1, 2]);
+        assert_eq!(report.folds.len(), 3);
+        assert_eq!(report.folds[0].history.epochs.len(), 20);
+        assert_eq!(report.values("val_accuracy").len(), 3);
+        assert!(report.mean("val_accuracy").unwrap() > 0.5);
+        assert!(report.std("val_loss").unwrap() >= 0.);
+        assert_eq!(report.mean("missing"), None);
+        let text = report.to_string();
+        assert!(text.starts_with("3-fold cross validation\n"));
+        assert!(text.contains("val_accuracy: "));
+
+        // 参数不合法时返回Err, 不会panic
+        let factory = |_| {
+            NeuralNetworkModel::sequential(2)
+                .dense_softmax(2)
+                .minimize(CrossEntropy::new())
+                .build()
+        };
+        // k为1时只有一折, 训练集为空
+        for k in [0, 1, 13] {
+            let cv = CrossValidation {
+                k,
+                ..Default::default()
+            };
+            let r = cross_validate(factory, &datas, &labels, &config, &cv);
+            assert!(matches!(r, Err(Error::InvalidParameter(_))));
+        }
+        let r = cross_validate(factory, &datas, &labels[..11], &config, &cv);
+        assert!(matches!(
+            r,
+            Err(Error::BatchSizeMismatch {
+                expected: 12,
+                actual: 11
+            })
+        ));
+    }
+}
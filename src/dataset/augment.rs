@@ -0,0 +1,620 @@
+use ndarray_rand::rand_distr::Normal;
+use rand::distributions::Distribution;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{train::SampleSource, Error, Float, Mat, Result};
+
+/// 增强时使用的图片, 按行存储, 多通道时每个像素的各通道连续排列
+/// 像素值是 0 - 1 的小数, 图片外的区域当作0
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub rows: usize,
+    pub cols: usize,
+    pub channels: usize,
+    pub data: Vec<f64>,
+}
+
+impl Image {
+    // data的长度必须是 rows * cols * channels
+    pub fn new(rows: usize, cols: usize, channels: usize, data: Vec<f64>) -> Result<Self> {
+        if rows * cols * channels != data.len() {
+            return Err(Error::InvalidParameter(format!(
+                "image of {}x{}x{} needs {} values, got {}",
+                rows,
+                cols,
+                channels,
+                rows * cols * channels,
+                data.len()
+            )));
+        }
+        Ok(Image {
+            rows,
+            cols,
+            channels,
+            data,
+        })
+    }
+
+    // 通道数根据长度推断, 比如 parse_imgs_from_reader 读出的灰度图
+    pub fn from_u8(img: &[u8], rows: usize, cols: usize) -> Result<Self> {
+        let data = img.iter().map(|v| *v as f64 / u8::MAX as f64).collect();
+        Self::new(rows, cols, img.len() / (rows * cols).max(1), data)
+    }
+
+    // 超出 0 - 1 的值会被截断
+    pub fn to_u8(&self) -> Vec<u8> {
+        self.data
+            .iter()
+            .map(|v| (v.clamp(0., 1.) * u8::MAX as f64).round() as u8)
+            .collect()
+    }
+
+    // n行1列的样本, 和 ImageFolder 的格式一致
+    pub fn from_mat<T: Float>(m: &Mat<T>, rows: usize, cols: usize) -> Result<Self> {
+        let data: Vec<f64> = m.iter().map(|v| v.to_f64().unwrap()).collect();
+        let channels = data.len() / (rows * cols).max(1);
+        Self::new(rows, cols, channels, data)
+    }
+
+    pub fn to_mat<T: Float>(&self) -> Mat<T> {
+        let data = self.data.iter().map(|v| T::of(*v)).collect();
+        Mat::from_shape_vec((self.data.len(), 1), data).unwrap()
+    }
+
+    fn get(&self, y: isize, x: isize, c: usize) -> f64 {
+        if y < 0 || x < 0 || y >= self.rows as isize || x >= self.cols as isize {
+            return 0.;
+        }
+        self.data[(y as usize * self.cols + x as usize) * self.channels + c]
+    }
+
+    // 双线性插值
+    fn sample(&self, y: f64, x: f64, c: usize) -> f64 {
+        let (y0, x0) = (y.floor(), x.floor());
+        let (dy, dx) = (y - y0, x - x0);
+        let (y0, x0) = (y0 as isize, x0 as isize);
+        self.get(y0, x0, c) * (1. - dy) * (1. - dx)
+            + self.get(y0, x0 + 1, c) * (1. - dy) * dx
+            + self.get(y0 + 1, x0, c) * dy * (1. - dx)
+            + self.get(y0 + 1, x0 + 1, c) * dy * dx
+    }
+
+    // 输出的每个像素 (y, x) 取原图 f(y, x) 位置的值
+    fn warp(&self, f: impl Fn(f64, f64) -> (f64, f64)) -> Image {
+        let mut data = Vec::with_capacity(self.data.len());
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                let (sy, sx) = f(y as f64, x as f64);
+                for c in 0..self.channels {
+                    data.push(self.sample(sy, sx, c));
+                }
+            }
+        }
+        Image { data, ..*self }
+    }
+
+    // 以图片中心为原点做线性变换, m是输出坐标到原图坐标的矩阵 [[yy, yx], [xy, xx]]
+    fn warp_center(&self, m: [[f64; 2]; 2]) -> Image {
+        let cy = (self.rows as f64 - 1.) / 2.;
+        let cx = (self.cols as f64 - 1.) / 2.;
+        self.warp(|y, x| {
+            let (y, x) = (y - cy, x - cx);
+            (
+                m[0][0] * y + m[0][1] * x + cy,
+                m[1][0] * y + m[1][1] * x + cx,
+            )
+        })
+    }
+}
+
+/// 随机变换, 每次调用从rng中取新的随机参数
+pub trait Transform: Send + Sync {
+    fn apply(&self, img: &mut Image, rng: &mut StdRng);
+}
+
+/// 平移, 横竖方向各在 [-max, max] 个像素中随机
+pub struct Translate {
+    max: f64,
+}
+
+impl Translate {
+    pub fn new(max: f64) -> Result<Self> {
+        non_negative("translate", max)?;
+        Ok(Translate { max })
+    }
+}
+
+impl Transform for Translate {
+    fn apply(&self, img: &mut Image, rng: &mut StdRng) {
+        let dy = rng.gen_range(-self.max..=self.max);
+        let dx = rng.gen_range(-self.max..=self.max);
+        *img = img.warp(|y, x| (y - dy, x - dx));
+    }
+}
+
+/// 绕中心旋转, 角度在 [-max_degrees, max_degrees] 中随机
+pub struct Rotate {
+    max_degrees: f64,
+}
+
+impl Rotate {
+    pub fn new(max_degrees: f64) -> Result<Self> {
+        non_negative("rotate", max_degrees)?;
+        Ok(Rotate { max_degrees })
+    }
+}
+
+impl Transform for Rotate {
+    fn apply(&self, img: &mut Image, rng: &mut StdRng) {
+        let a = rng
+            .gen_range(-self.max_degrees..=self.max_degrees)
+            .to_radians();
+        let (sin, cos) = a.sin_cos();
+        *img = img.warp_center([[cos, -sin], [sin, cos]]);
+    }
+}
+
+/// 以中心缩放, 比例在 [min, max] 中随机, 大于1时放大
+pub struct Scale {
+    min: f64,
+    max: f64,
+}
+
+impl Scale {
+    // 0 < min <= max
+    pub fn new(min: f64, max: f64) -> Result<Self> {
+        range("scale", min, max)?;
+        if min <= 0. {
+            return Err(Error::InvalidParameter(format!(
+                "scale: min must be positive, got {}",
+                min
+            )));
+        }
+        Ok(Scale { min, max })
+    }
+}
+
+impl Transform for Scale {
+    fn apply(&self, img: &mut Image, rng: &mut StdRng) {
+        let s = rng.gen_range(self.min..=self.max);
+        *img = img.warp_center([[1. / s, 0.], [0., 1. / s]]);
+    }
+}
+
+/// 水平方向错切, 系数在 [-max, max] 中随机
+pub struct Shear {
+    max: f64,
+}
+
+impl Shear {
+    pub fn new(max: f64) -> Result<Self> {
+        non_negative("shear", max)?;
+        Ok(Shear { max })
+    }
+}
+
+impl Transform for Shear {
+    fn apply(&self, img: &mut Image, rng: &mut StdRng) {
+        let k = rng.gen_range(-self.max..=self.max);
+        *img = img.warp_center([[1., 0.], [k, 1.]]);
+    }
+}
+
+/// 弹性形变: 随机位移场经过高斯平滑后乘以alpha
+/// MNIST上常用 alpha = 34, sigma = 4
+pub struct Elastic {
+    alpha: f64,
+    sigma: f64,
+}
+
+impl Elastic {
+    pub fn new(alpha: f64, sigma: f64) -> Result<Self> {
+        non_negative("elastic alpha", alpha)?;
+        non_negative("elastic sigma", sigma)?;
+        Ok(Elastic { alpha, sigma })
+    }
+}
+
+impl Transform for Elastic {
+    fn apply(&self, img: &mut Image, rng: &mut StdRng) {
+        let (rows, cols) = (img.rows, img.cols);
+        let mut field = || {
+            let f: Vec<f64> = (0..rows * cols).map(|_| rng.gen_range(-1.0..=1.)).collect();
+            gaussian_blur(&f, rows, cols, self.sigma)
+        };
+        let (dy, dx) = (field(), field());
+        let alpha = self.alpha;
+        *img = img.warp(|y, x| {
+            let i = y as usize * cols + x as usize;
+            (y + alpha * dy[i], x + alpha * dx[i])
+        });
+    }
+}
+
+// 可分离的高斯模糊, 边界外当作0
+fn gaussian_blur(f: &[f64], rows: usize, cols: usize, sigma: f64) -> Vec<f64> {
+    if sigma <= 0. {
+        return f.to_vec();
+    }
+    let r = (sigma * 3.).ceil() as isize;
+    let kernel: Vec<f64> = (-r..=r)
+        .map(|d| (-(d * d) as f64 / (2. * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    let kernel: Vec<f64> = kernel.iter().map(|k| k / sum).collect();
+    let pass = |src: &[f64], horizontal: bool| -> Vec<f64> {
+        let mut out = vec![0.; src.len()];
+        for y in 0..rows as isize {
+            for x in 0..cols as isize {
+                let mut v = 0.;
+                for (k, d) in kernel.iter().zip(-r..=r) {
+                    let (sy, sx) = if horizontal { (y, x + d) } else { (y + d, x) };
+                    if sy >= 0 && sx >= 0 && sy < rows as isize && sx < cols as isize {
+                        v += k * src[sy as usize * cols + sx as usize];
+                    }
+                }
+                out[y as usize * cols + x as usize] = v;
+            }
+        }
+        out
+    };
+    pass(&pass(f, true), false)
+}
+
+/// 每个像素加上均值为0, 标准差为std的高斯噪声
+pub struct GaussianNoise {
+    normal: Normal<f64>,
+}
+
+impl GaussianNoise {
+    pub fn new(std: f64) -> Result<Self> {
+        non_negative("noise std", std)?;
+        Ok(GaussianNoise {
+            normal: Normal::new(0., std).unwrap(),
+        })
+    }
+}
+
+impl Transform for GaussianNoise {
+    fn apply(&self, img: &mut Image, rng: &mut StdRng) {
+        for v in img.data.iter_mut() {
+            *v += self.normal.sample(rng);
+        }
+    }
+}
+
+/// 以概率p把一个随机矩形区域填成value (cutout)
+/// area是矩形面积占整张图片的比例范围, 宽高比在 [0.3, 3.3] 中随机
+pub struct RandomErasing {
+    p: f64,
+    area: (f64, f64),
+    value: f64,
+}
+
+impl RandomErasing {
+    // p在 [0, 1] 中, 0 <= area.0 <= area.1
+    pub fn new(p: f64, area: (f64, f64), value: f64) -> Result<Self> {
+        if !(0. ..=1.).contains(&p) {
+            return Err(Error::InvalidParameter(format!(
+                "erasing: probability must be in [0, 1], got {}",
+                p
+            )));
+        }
+        range("erasing area", area.0, area.1)?;
+        non_negative("erasing area", area.0)?;
+        Ok(RandomErasing { p, area, value })
+    }
+}
+
+impl Transform for RandomErasing {
+    fn apply(&self, img: &mut Image, rng: &mut StdRng) {
+        // 空图片没有可以擦除的区域
+        if img.rows == 0 || img.cols == 0 || !rng.gen_bool(self.p) {
+            return;
+        }
+        let area = rng.gen_range(self.area.0..=self.area.1) * (img.rows * img.cols) as f64;
+        let ratio = rng.gen_range(0.3f64.ln()..=3.3f64.ln()).exp();
+        let h = ((area * ratio).sqrt().round() as usize).clamp(1, img.rows);
+        let w = ((area / ratio).sqrt().round() as usize).clamp(1, img.cols);
+        let top = rng.gen_range(0..=img.rows - h);
+        let left = rng.gen_range(0..=img.cols - w);
+        for y in top..top + h {
+            let start = (y * img.cols + left) * img.channels;
+            img.data[start..start + w * img.channels].fill(self.value);
+        }
+    }
+}
+
+/// 亮度和对比度抖动: v = (v - 均值) * c + 均值 + b
+/// c在 [1 - contrast, 1 + contrast] 中随机, b在 [-brightness, brightness] 中随机
+pub struct BrightnessContrast {
+    brightness: f64,
+    contrast: f64,
+}
+
+impl BrightnessContrast {
+    pub fn new(brightness: f64, contrast: f64) -> Result<Self> {
+        non_negative("brightness", brightness)?;
+        non_negative("contrast", contrast)?;
+        Ok(BrightnessContrast {
+            brightness,
+            contrast,
+        })
+    }
+}
+
+impl Transform for BrightnessContrast {
+    fn apply(&self, img: &mut Image, rng: &mut StdRng) {
+        let b = rng.gen_range(-self.brightness..=self.brightness);
+        let c = rng.gen_range(1. - self.contrast..=1. + self.contrast);
+        let mean = img.data.iter().sum::<f64>() / img.data.len().max(1) as f64;
+        for v in img.data.iter_mut() {
+            *v = (*v - mean) * c + mean + b;
+        }
+    }
+}
+
+// 随机范围的上下限必须是有限的数
+fn range(name: &str, low: f64, high: f64) -> Result<()> {
+    if !low.is_finite() || !high.is_finite() || low > high {
+        return Err(Error::InvalidParameter(format!(
+            "{}: invalid range [{}, {}]",
+            name, low, high
+        )));
+    }
+    Ok(())
+}
+
+fn non_negative(name: &str, v: f64) -> Result<()> {
+    if !v.is_finite() || v < 0. {
+        return Err(Error::InvalidParameter(format!(
+            "{}: expects a non-negative number, got {}",
+            name, v
+        )));
+    }
+    Ok(())
+}
+
+/// 按顺序执行的一组随机变换, 参数不合法时返回Err
+/// Augmenter::new().translate(2.)?.rotate(10.)?.noise(0.05)?
+#[derive(Default)]
+pub struct Augmenter {
+    transforms: Vec<Box<dyn Transform>>,
+}
+
+impl Augmenter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(mut self, t: impl Transform + 'static) -> Self {
+        self.transforms.push(Box::new(t));
+        self
+    }
+
+    pub fn translate(self, max: f64) -> Result<Self> {
+        Ok(self.push(Translate::new(max)?))
+    }
+
+    pub fn rotate(self, max_degrees: f64) -> Result<Self> {
+        Ok(self.push(Rotate::new(max_degrees)?))
+    }
+
+    pub fn scale(self, min: f64, max: f64) -> Result<Self> {
+        Ok(self.push(Scale::new(min, max)?))
+    }
+
+    pub fn shear(self, max: f64) -> Result<Self> {
+        Ok(self.push(Shear::new(max)?))
+    }
+
+    pub fn elastic(self, alpha: f64, sigma: f64) -> Result<Self> {
+        Ok(self.push(Elastic::new(alpha, sigma)?))
+    }
+
+    pub fn noise(self, std: f64) -> Result<Self> {
+        Ok(self.push(GaussianNoise::new(std)?))
+    }
+
+    pub fn erasing(self, p: f64, area: (f64, f64)) -> Result<Self> {
+        Ok(self.push(RandomErasing::new(p, area, 0.)?))
+    }
+
+    pub fn brightness_contrast(self, brightness: f64, contrast: f64) -> Result<Self> {
+        Ok(self.push(BrightnessContrast::new(brightness, contrast)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.transforms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    pub fn apply(&self, img: &mut Image, rng: &mut StdRng) {
+        for t in &self.transforms {
+            t.apply(img, rng);
+        }
+    }
+
+    // 灰度或多通道的u8图片, 结果截断到 0 - 255
+    pub fn augment_u8(
+        &self,
+        img: &[u8],
+        rows: usize,
+        cols: usize,
+        rng: &mut StdRng,
+    ) -> Result<Vec<u8>> {
+        let mut img = Image::from_u8(img, rows, cols)?;
+        self.apply(&mut img, rng);
+        Ok(img.to_u8())
+    }
+
+    // 已经缩放到 0 - 1 的样本, 结果不截断
+    pub fn augment_mat<T: Float>(
+        &self,
+        m: &Mat<T>,
+        rows: usize,
+        cols: usize,
+        rng: &mut StdRng,
+    ) -> Result<Mat<T>> {
+        let mut img = Image::from_mat(m, rows, cols)?;
+        self.apply(&mut img, rng);
+        Ok(img.to_mat())
+    }
+}
+
+/// 包装一个SampleSource, 取样本时对输入做随机增强, 期望结果不变
+/// 随机数只由 seed, 轮次和样本下标决定, 和取样本的顺序无关, 从checkpoint恢复后结果一样
+pub struct Augmented<S> {
+    source: S,
+    augmenter: Augmenter,
+    rows: usize,
+    cols: usize,
+    seed: u64,
+    epoch: usize,
+}
+
+impl<S> Augmented<S> {
+    pub fn new(source: S, augmenter: Augmenter, rows: usize, cols: usize, seed: u64) -> Self {
+        Augmented {
+            source,
+            augmenter,
+            rows,
+            cols,
+            seed,
+            epoch: 0,
+        }
+    }
+
+    fn rng(&self, i: usize) -> StdRng {
+        StdRng::seed_from_u64(
+            self.seed
+                .wrapping_add((self.epoch as u64) << 32)
+                .wrapping_add(i as u64),
+        )
+    }
+}
+
+impl<T: Float, S: SampleSource<T>> SampleSource<T> for Augmented<S> {
+    fn len(&self) -> usize {
+        self.source.len()
+    }
+    fn get(&mut self, i: usize) -> Result<(Mat<T>, Mat<T>)> {
+        let (data, label) = self.source.get(i)?;
+        let data = self
+            .augmenter
+            .augment_mat(&data, self.rows, self.cols, &mut self.rng(i))?;
+        Ok((data, label))
+    }
+    fn start_epoch(&mut self, epoch: usize) {
+        self.epoch = epoch;
+        self.source.start_epoch(epoch);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::{train::SampleSource, Mat};
+
+    use super::{Augmented, Augmenter, Image};
+
+    #[test]
+    fn test() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // 3x3的图片, 中间一个亮点
+        let mut img = vec![0u8; 9];
+        img[4] = 255;
+
+        // 变换幅度为0时图片不变
+        let identity = Augmenter::new()
+            .translate(0.)
+            .unwrap()
+            .rotate(0.)
+            .unwrap()
+            .scale(1., 1.)
+            .unwrap()
+            .shear(0.)
+            .unwrap()
+            .elastic(0., 1.)
+            .unwrap()
+            .brightness_contrast(0., 0.)
+            .unwrap();
+        assert_eq!(identity.len(), 6);
+        assert_eq!(identity.augment_u8(&img, 3, 3, &mut rng).unwrap(), img);
+
+        // 转回u8时截断到 0 - 255
+        let out = Image::new(1, 2, 1, vec![-0.5, 1.5]).unwrap().to_u8();
+        assert_eq!(out, vec![0, 255]);
+
+        // 同一个种子结果一样
+        let aug = Augmenter::new()
+            .translate(1.)
+            .and_then(|a| a.rotate(30.))
+            .and_then(|a| a.elastic(2., 1.))
+            .and_then(|a| a.noise(0.1))
+            .unwrap();
+        let a = aug.augment_u8(&img, 3, 3, &mut StdRng::seed_from_u64(1));
+        let b = aug.augment_u8(&img, 3, 3, &mut StdRng::seed_from_u64(1));
+        let c = aug.augment_u8(&img, 3, 3, &mut StdRng::seed_from_u64(2));
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+
+        // 必定擦除, 面积是整张图
+        let erase = Augmenter::new().erasing(1., (1., 1.)).unwrap();
+        let m: Mat = Mat::ones((12, 1));
+        let out = erase.augment_mat(&m, 2, 2, &mut rng).unwrap();
+        assert_eq!(out.dim(), (12, 1));
+        assert!(out.iter().any(|v| *v == 0.));
+        assert_eq!(Image::from_mat(&m, 2, 2).unwrap().channels, 3);
+        // 空图片不擦除
+        assert!(erase.augment_u8(&[], 0, 2, &mut rng).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid() {
+        // 参数不合法时返回Err, 不会在取样本时panic
+        assert!(Image::new(2, 2, 1, vec![0.; 3]).is_err());
+        assert!(Image::from_u8(&[0; 5], 2, 2).is_err());
+        assert!(Augmenter::new().translate(-1.).is_err());
+        assert!(Augmenter::new().rotate(f64::NAN).is_err());
+        assert!(Augmenter::new().scale(2., 1.).is_err());
+        assert!(Augmenter::new().scale(0., 1.).is_err());
+        assert!(Augmenter::new().shear(-0.1).is_err());
+        assert!(Augmenter::new().elastic(1., -1.).is_err());
+        assert!(Augmenter::new().noise(-0.1).is_err());
+        assert!(Augmenter::new().erasing(1.5, (0.1, 0.2)).is_err());
+        assert!(Augmenter::new().erasing(0.5, (0.3, 0.2)).is_err());
+        assert!(Augmenter::new().brightness_contrast(0.1, -0.1).is_err());
+    }
+
+    #[test]
+    fn test_source() {
+        let datas: Vec<Mat> = vec![array![[0.], [1.], [0.5], [0.2]]; 3];
+        let labels: Vec<Mat> = vec![array![[1.]]; 3];
+        let mut source = Augmented::new(
+            (&datas[..], &labels[..]),
+            Augmenter::new().noise(0.1).unwrap(),
+            2,
+            2,
+            7,
+        );
+        source.start_epoch(0);
+        let first: Vec<Mat> = (0..3).map(|i| source.get(i).unwrap().0).collect();
+        // 倒序取出, 每个样本的结果不变
+        for i in (0..3).rev() {
+            assert_eq!(source.get(i).unwrap().0, first[i]);
+        }
+        assert_ne!(first[0], first[1]);
+        assert_eq!(source.get(0).unwrap().1, labels[0]);
+
+        source.start_epoch(1);
+        assert_ne!(source.get(0).unwrap().0, first[0]);
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::{dataset::one_hot, Float, Mat};
+
+/// 特征的缩放方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    None,
+    // 除以一个常数, 像素值用 Divide(255.) 转换为 0 - 1 的小数
+    Divide(f64),
+}
+
+/// CSV数据集的读取配置, 默认是Kaggle digit-recognizer的格式
+#[derive(Debug, Clone)]
+pub struct CsvConfig {
+    // 标签所在的列, None表示没有标签(比如Kaggle的test.csv)
+    pub label_column: Option<usize>,
+    // 第一个非空行是否是列名
+    pub has_header: bool,
+    pub delimiter: char,
+    pub scaling: Scaling,
+    // 类别个数, 即one-hot目标的行数, None时根据标签推断
+    // 标签是整数时直接作为类别编号, 必须小于num_classes
+    pub num_classes: Option<usize>,
+}
+
+impl Default for CsvConfig {
+    fn default() -> Self {
+        CsvConfig {
+            label_column: Some(0),
+            has_header: true,
+            delimiter: ',',
+            scaling: Scaling::Divide(255.),
+            // digit-recognizer的标签是0到9
+            num_classes: Some(10),
+        }
+    }
+}
+
+/// 从CSV读出的数据集, 每行一个样本
+/// 样本是n行1列的Mat, 期望结果是one-hot的Mat, 和main中的格式一致
+pub struct CsvDataset<T: Float = f32> {
+    // 特征列的列名, 没有表头时为空
+    pub columns: Vec<String>,
+    pub datas: Vec<Mat<T>>,
+    // 每个样本的类别编号, 没有标签列时为空
+    pub labels: Vec<usize>,
+    pub targets: Vec<Mat<T>>,
+    // 类别名, 下标就是类别编号
+    // 指定了num_classes且标签都是整数时就是 "0", "1", ...; 否则是排序后出现过的所有标签
+    pub classes: Vec<String>,
+}
+
+impl<T: Float> CsvDataset<T> {
+    pub fn load(path: impl AsRef<Path>, config: &CsvConfig) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?), config)
+    }
+
+    pub fn read<R: BufRead>(reader: R, config: &CsvConfig) -> io::Result<Self> {
+        let mut columns = vec![];
+        let mut datas = vec![];
+        let mut raw_labels = vec![];
+        let mut width = None;
+        let mut header = config.has_header;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            // 只去掉行尾的换行, 开头结尾的分隔符(比如tab)属于空字段
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut fields = split_fields(line, config.delimiter).map_err(|e| invalid(i, e))?;
+            let label = match config.label_column {
+                Some(c) if c < fields.len() => Some(fields.remove(c)),
+                Some(c) => return Err(invalid(i, format!("no label column {}", c))),
+                None => None,
+            };
+            // 表头是第一个非空行
+            if header {
+                header = false;
+                width = Some(fields.len());
+                columns = fields;
+                continue;
+            }
+            if *width.get_or_insert(fields.len()) != fields.len() {
+                return Err(invalid(
+                    i,
+                    format!("expects {} features, got {}", width.unwrap(), fields.len()),
+                ));
+            }
+            let mut data = Vec::with_capacity(fields.len());
+            for f in &fields {
+                let v: f64 = f
+                    .parse()
+                    .map_err(|_| invalid(i, format!("invalid number {:?}", f)))?;
+                data.push(T::of(match config.scaling {
+                    Scaling::None => v,
+                    Scaling::Divide(d) => v / d,
+                }));
+            }
+            let n = data.len();
+            datas.push(Mat::from_shape_vec((n, 1), data).unwrap());
+            if let Some(label) = label {
+                raw_labels.push(label);
+            }
+        }
+
+        let (labels, classes) = encode_labels(&raw_labels, config.num_classes)?;
+        let targets = labels.iter().map(|l| one_hot(*l, classes.len())).collect();
+        Ok(CsvDataset {
+            columns,
+            datas,
+            labels,
+            targets,
+            classes,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.datas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.datas.is_empty()
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.classes.len()
+    }
+}
+
+// 指定了类别个数且标签都是非负整数时直接作为类别编号
+// 否则只对出现过的标签编号, 整数按数值排序, 其他按名字排序
+fn encode_labels(
+    raw: &[String],
+    num_classes: Option<usize>,
+) -> io::Result<(Vec<usize>, Vec<String>)> {
+    let ints: Option<Vec<usize>> = raw.iter().map(|l| l.parse().ok()).collect();
+    let (labels, classes) = match (ints, num_classes) {
+        (Some(ints), Some(n)) => {
+            if let Some(l) = ints.iter().find(|l| **l >= n) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("label {} is out of range for {} classes", l, n),
+                ));
+            }
+            (ints, (0..n).map(|i| i.to_string()).collect())
+        }
+        (Some(ints), None) => {
+            let values: Vec<usize> = ints
+                .iter()
+                .copied()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            let labels = ints
+                .iter()
+                .map(|l| values.binary_search(l).unwrap())
+                .collect();
+            (labels, values.iter().map(|v| v.to_string()).collect())
+        }
+        (None, _) => {
+            let names: BTreeSet<&String> = raw.iter().collect();
+            let classes: Vec<String> = names.into_iter().cloned().collect();
+            let labels = raw
+                .iter()
+                .map(|l| classes.binary_search(l).unwrap())
+                .collect();
+            (labels, classes)
+        }
+    };
+    if let Some(n) = num_classes {
+        if classes.len() > n {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("found {} classes, expects at most {}", classes.len(), n),
+            ));
+        }
+    }
+    Ok((labels, classes))
+}
+
+// 按分隔符切分一行, 去掉两边的空白
+// 双引号中的分隔符不切分, 两个双引号表示一个双引号, 不支持跨行的字段
+fn split_fields(line: &str, delimiter: char) -> Result<Vec<String>, String> {
+    let mut fields = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        while chars
+            .peek()
+            .is_some_and(|c| *c != delimiter && c.is_whitespace())
+        {
+            chars.next();
+        }
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quoted field".to_string()),
+                }
+            }
+            while chars
+                .peek()
+                .is_some_and(|c| *c != delimiter && c.is_whitespace())
+            {
+                chars.next();
+            }
+            match chars.next() {
+                Some(c) if c == delimiter => fields.push(field),
+                None => {
+                    fields.push(field);
+                    return Ok(fields);
+                }
+                Some(c) => return Err(format!("unexpected {:?} after quoted field", c)),
+            }
+        } else {
+            loop {
+                match chars.next() {
+                    Some(c) if c == delimiter => break,
+                    Some('"') => return Err("unexpected '\"' in unquoted field".to_string()),
+                    Some(c) => field.push(c),
+                    None => {
+                        fields.push(field.trim_end().to_string());
+                        return Ok(fields);
+                    }
+                }
+            }
+            fields.push(field.trim_end().to_string());
+        }
+    }
+}
+
+fn invalid(line: usize, msg: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line + 1, msg),
+    )
+}
+
+// Kaggle提交格式, 比如digit-recognizer是 ImageId,Label, 编号从1开始
+pub fn write_submission<W: Write>(
+    w: &mut W,
+    columns: (&str, &str),
+    predictions: &[usize],
+) -> io::Result<()> {
+    writeln!(w, "{},{}", columns.0, columns.1)?;
+    for (i, p) in predictions.iter().enumerate() {
+        writeln!(w, "{},{}", i + 1, p)?;
+    }
+    Ok(())
+}
+
+pub fn save_submission(path: impl AsRef<Path>, predictions: &[usize]) -> io::Result<()> {
+    let mut f = BufWriter::new(File::create(path)?);
+    write_submission(&mut f, ("ImageId", "Label"), predictions)?;
+    f.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use super::{write_submission, CsvConfig, CsvDataset, Scaling};
+
+    #[test]
+    fn test() {
+        let csv = "label,pixel0,pixel1\n1,0,255\n\n0,51,102\n";
+        let ds: CsvDataset = CsvDataset::read(csv.as_bytes(), &CsvConfig::default()).unwrap();
+        assert_eq!(ds.columns, vec!["pixel0", "pixel1"]);
+        assert_eq!(ds.len(), 2);
+        assert_eq!(ds.datas[0], array![[0.], [1.]]);
+        assert_eq!(ds.datas[1], array![[0.2], [0.4]]);
+        assert_eq!(ds.labels, vec![1, 0]);
+        assert_eq!(ds.num_classes(), 10);
+        assert_eq!(ds.targets[0].dim(), (10, 1));
+        assert_eq!(ds.targets[0][[1, 0]], 1.);
+        assert_eq!(ds.targets[0].sum(), 1.);
+
+        // 制表符分隔时开头结尾的空字段不能被去掉
+        let config = CsvConfig {
+            has_header: false,
+            delimiter: '\t',
+            scaling: Scaling::None,
+            ..Default::default()
+        };
+        let err = CsvDataset::<f32>::read("3\t1\t\r\n".as_bytes(), &config)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "line 1: invalid number \"\"");
+        let config = CsvConfig {
+            label_column: Some(2),
+            ..config
+        };
+        let err = CsvDataset::<f32>::read("\t1\t3\n".as_bytes(), &config)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "line 1: invalid number \"\"");
+
+        // 没有标签的测试集, 类别数固定为10
+        let config = CsvConfig {
+            label_column: None,
+            num_classes: Some(10),
+            ..Default::default()
+        };
+        let ds: CsvDataset = CsvDataset::read("pixel0\n3\n".as_bytes(), &config).unwrap();
+        assert!(ds.labels.is_empty());
+        assert_eq!(ds.num_classes(), 10);
+
+        // 表格数据: 分号分隔, 标签在最后一列, 不缩放
+        let config = CsvConfig {
+            label_column: Some(2),
+            has_header: false,
+            delimiter: ';',
+            scaling: Scaling::None,
+            num_classes: None,
+        };
+        let csv = "1.5;2;setosa\n3;-4;virginica\n0;0;setosa\n";
+        let ds: CsvDataset<f64> = CsvDataset::read(csv.as_bytes(), &config).unwrap();
+        assert!(ds.columns.is_empty());
+        assert_eq!(ds.datas[1], array![[3.], [-4.]]);
+        assert_eq!(ds.classes, vec!["setosa", "virginica"]);
+        assert_eq!(ds.labels, vec![0, 1, 0]);
+
+        let err = CsvDataset::<f32>::read("1;2;a\n1;x;b\n".as_bytes(), &config)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "line 2: invalid number \"x\"");
+        let err = CsvDataset::<f32>::read("1;2;a\n1;2;3;b\n".as_bytes(), &config)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "line 2: expects 2 features, got 3");
+        let err = CsvDataset::<f32>::read("1;2;a\n1;b\n".as_bytes(), &config)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "line 2: no label column 2");
+
+        // 开头的空行不影响表头, 引号中的逗号不切分
+        let csv = "\n\"label\",\"a, b\",c\n\"x,y\", 1 ,\"2\"\n";
+        let config = CsvConfig {
+            scaling: Scaling::None,
+            ..Default::default()
+        };
+        let ds: CsvDataset = CsvDataset::read(csv.as_bytes(), &config).unwrap();
+        assert_eq!(ds.columns, vec!["a, b", "c"]);
+        assert_eq!(ds.classes, vec!["x,y"]);
+        assert_eq!(ds.datas[0], array![[1.], [2.]]);
+        let err = CsvDataset::<f32>::read("l,a\n\"1,2\n".as_bytes(), &config)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "line 2: unterminated quoted field");
+        let err = CsvDataset::<f32>::read("l,a\n1,2\"3\n".as_bytes(), &config)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "line 2: unexpected '\"' in unquoted field");
+
+        // 整数标签只对出现过的值编号, 不会按最大值分配类别
+        let config = CsvConfig {
+            num_classes: None,
+            ..config
+        };
+        let csv = "l,a\n1000000,1\n7,2\n1000000,3\n";
+        let ds: CsvDataset = CsvDataset::read(csv.as_bytes(), &config).unwrap();
+        assert_eq!(ds.classes, vec!["7", "1000000"]);
+        assert_eq!(ds.labels, vec![1, 0, 1]);
+        assert_eq!(ds.targets[0].dim(), (2, 1));
+        // 指定了类别个数时标签就是编号, 超出范围报错
+        let config = CsvConfig {
+            num_classes: Some(10),
+            ..config
+        };
+        let ds: CsvDataset = CsvDataset::read("l,a\n3,1\n".as_bytes(), &config).unwrap();
+        assert_eq!(ds.labels, vec![3]);
+        assert_eq!(ds.num_classes(), 10);
+        let err = CsvDataset::<f32>::read("l,a\n10,1\n".as_bytes(), &config)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "label 10 is out of range for 10 classes");
+
+        let mut out = vec![];
+        write_submission(&mut out, ("ImageId", "Label"), &[2, 0, 9]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ImageId,Label\n1,2\n2,0\n3,9\n"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use mnist_data_loader::image::{self, imageops::FilterType, DynamicImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{dataset::one_hot, Float, Mat};
+
+/// 读取图片时转换的颜色格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorMode {
+    // 每个像素1个值
+    Gray,
+    // 每个像素3个值, 按 r, g, b 依次排列
+    Rgb,
+}
+
+impl ColorMode {
+    pub fn channels(self) -> usize {
+        match self {
+            ColorMode::Gray => 1,
+            ColorMode::Rgb => 3,
+        }
+    }
+}
+
+/// 图片目录数据集的读取配置
+#[derive(Debug, Clone)]
+pub struct ImageFolderConfig {
+    pub color: ColorMode,
+    // 缩放后的 (宽, 高), None时不缩放, 但所有图片的大小必须一样
+    pub size: Option<(u32, u32)>,
+}
+
+impl Default for ImageFolderConfig {
+    // 和MNIST一样, 28x28的灰度图
+    fn default() -> Self {
+        ImageFolderConfig {
+            color: ColorMode::Gray,
+            size: Some((28, 28)),
+        }
+    }
+}
+
+/// 类别名和编号的对应关系, 下标就是类别编号
+/// 和模型一起保存, 推理时用来把输出转换回类别名
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClassMap {
+    pub classes: Vec<String>,
+}
+
+impl ClassMap {
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.classes.iter().position(|c| c == name)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// 按 root/<类别名>/*.png|jpg 组织的图片数据集
+/// 每张图片转换成 高*宽*通道数 行1列的Mat, 像素值缩放到 0 - 1
+pub struct ImageFolder<T: Float = f32> {
+    pub class_map: ClassMap,
+    pub paths: Vec<PathBuf>,
+    pub labels: Vec<usize>,
+    pub datas: Vec<Mat<T>>,
+    pub width: u32,
+    pub height: u32,
+    pub color: ColorMode,
+}
+
+impl<T: Float> ImageFolder<T> {
+    // 类别是root下所有子目录的名字, 按名字排序后编号
+    pub fn load(root: impl AsRef<Path>, config: &ImageFolderConfig) -> io::Result<Self> {
+        let mut classes = vec![];
+        for entry in std::fs::read_dir(root.as_ref())? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                classes.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        classes.sort();
+        Self::load_with_classes(root, config, &ClassMap { classes })
+    }
+
+    // 使用已有的类别编号, 比如读取测试集时用训练集保存的ClassMap
+    // root下不在class_map里的目录会被忽略
+    pub fn load_with_classes(
+        root: impl AsRef<Path>,
+        config: &ImageFolderConfig,
+        class_map: &ClassMap,
+    ) -> io::Result<Self> {
+        let mut paths = vec![];
+        let mut labels = vec![];
+        for (label, class) in class_map.classes.iter().enumerate() {
+            let dir = root.as_ref().join(class);
+            if !dir.is_dir() {
+                continue;
+            }
+            let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<io::Result<_>>()?;
+            files.retain(|p| is_image(p));
+            files.sort();
+            labels.extend(std::iter::repeat_n(label, files.len()));
+            paths.extend(files);
+        }
+
+        let mut datas = Vec::with_capacity(paths.len());
+        let mut size = config.size;
+        for path in &paths {
+            let img = image::open(path).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })?;
+            let (width, height) = *size.get_or_insert((img.width(), img.height()));
+            let img = if (img.width(), img.height()) != (width, height) {
+                if config.size.is_none() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{}: size {}x{} does not match {}x{}",
+                            path.display(),
+                            img.width(),
+                            img.height(),
+                            width,
+                            height
+                        ),
+                    ));
+                }
+                img.resize_exact(width, height, FilterType::Triangle)
+            } else {
+                img
+            };
+            datas.push(to_mat(&img, config.color));
+        }
+
+        let (width, height) = size.unwrap_or((0, 0));
+        Ok(ImageFolder {
+            class_map: class_map.clone(),
+            paths,
+            labels,
+            datas,
+            width,
+            height,
+            color: config.color,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.datas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.datas.is_empty()
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.class_map.classes.len()
+    }
+
+    // one-hot的期望结果
+    pub fn targets(&self) -> Vec<Mat<T>> {
+        self.labels
+            .iter()
+            .map(|l| one_hot(*l, self.num_classes()))
+            .collect()
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    matches!(ext.as_deref(), Some("png" | "jpg" | "jpeg"))
+}
+
+fn to_mat<T: Float>(img: &DynamicImage, color: ColorMode) -> Mat<T> {
+    let pxs = match color {
+        ColorMode::Gray => img.to_luma8().into_raw(),
+        ColorMode::Rgb => img.to_rgb8().into_raw(),
+    };
+    let n = pxs.len();
+    let data = pxs
+        .into_iter()
+        .map(|v| T::of(v as f64 / u8::MAX as f64))
+        .collect();
+    Mat::from_shape_vec((n, 1), data).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use mnist_data_loader::image::{Rgb, RgbImage};
+
+    use super::{ClassMap, ColorMode, ImageFolder, ImageFolderConfig};
+
+    #[test]
+    fn test() {
+        let root = std::env::temp_dir().join(format!("hello-nn-folder-{}", std::process::id()));
+        for class in ["seven", "one"] {
+            std::fs::create_dir_all(root.join(class)).unwrap();
+        }
+        RgbImage::from_pixel(8, 8, Rgb([255, 0, 0]))
+            .save(root.join("seven/a.png"))
+            .unwrap();
+        RgbImage::from_pixel(4, 4, Rgb([255, 255, 255]))
+            .save(root.join("one/b.PNG"))
+            .unwrap();
+        RgbImage::from_pixel(4, 4, Rgb([0, 0, 0]))
+            .save(root.join("one/a.jpg"))
+            .unwrap();
+        std::fs::write(root.join("one/notes.txt"), "not an image").unwrap();
+
+        let config = ImageFolderConfig {
+            color: ColorMode::Rgb,
+            size: Some((2, 3)),
+        };
+        let ds: ImageFolder = ImageFolder::load(&root, &config).unwrap();
+        assert_eq!(ds.class_map.classes, vec!["one", "seven"]);
+        assert_eq!(ds.labels, vec![0, 0, 1]);
+        assert!(ds.paths[0].ends_with("one/a.jpg"));
+        assert_eq!(ds.datas[2].dim(), (2 * 3 * 3, 1));
+        assert_eq!(ds.datas[2][(0, 0)], 1.);
+        assert_eq!(ds.datas[2][(1, 0)], 0.);
+        assert_eq!(ds.targets()[2][(1, 0)], 1.);
+
+        // 灰度图, 不缩放时大小不一致会报错
+        let config = ImageFolderConfig {
+            color: ColorMode::Gray,
+            size: None,
+        };
+        assert!(ImageFolder::<f32>::load(&root, &config).is_err());
+        let map = ClassMap {
+            classes: vec!["one".to_string(), "two".to_string()],
+        };
+        let ds: ImageFolder = ImageFolder::load_with_classes(&root, &config, &map).unwrap();
+        assert_eq!((ds.width, ds.height), (4, 4));
+        assert_eq!(ds.datas[1].dim(), (16, 1));
+        assert_eq!(ds.datas[1][(5, 0)], 1.);
+
+        map.save(root.join("classes.json")).unwrap();
+        let back = ClassMap::load(root.join("classes.json")).unwrap();
+        assert_eq!(back, map);
+        assert_eq!(back.index("two"), Some(1));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
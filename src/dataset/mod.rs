@@ -0,0 +1,24 @@
+mod augment;
+pub use augment::{
+    Augmented, Augmenter, BrightnessContrast, Elastic, GaussianNoise, Image, RandomErasing, Rotate,
+    Scale, Shear, Transform, Translate,
+};
+mod csv;
+pub use csv::{save_submission, write_submission, CsvConfig, CsvDataset, Scaling};
+mod image_folder;
+pub use image_folder::{ClassMap, ColorMode, ImageFolder, ImageFolderConfig};
+mod normalize;
+pub use normalize::{NormalizeKind, Normalized, Normalizer};
+mod split;
+pub use split::{
+    balanced_subset, k_fold, select, split, stratified_subset, Split, SplitConfig, SplitSize,
+};
+
+use crate::{Float, Mat};
+
+// 类别编号转换为one-hot的期望结果, classes行1列
+pub fn one_hot<T: Float>(label: usize, classes: usize) -> Mat<T> {
+    let mut l = Mat::zeros((classes, 1));
+    l[(label, 0)] = T::one();
+    l
+}
@@ -0,0 +1,513 @@
+use std::borrow::Borrow;
+use std::io;
+use std::path::Path;
+
+use ndarray::{Array1, Array2, Axis};
+use serde::{Deserialize, Serialize};
+
+use crate::{train::SampleSource, Error, Float, Mat, Result};
+
+/// 归一化的方式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NormalizeKind {
+    // 每个特征缩放到 0 - 1
+    MinMax,
+    // 每个特征减去均值再除以标准差
+    Standardize,
+    // 所有特征共用一个均值和标准差, 比如MNIST的 0.1307 / 0.3081
+    GlobalStandardize,
+    // PCA白化: 投影到前components个主成分上, 每个方向方差为1
+    // components为None时保留所有方向, epsilon必须大于0, 防止除以很小或为0的特征值
+    // (比如MNIST边缘总是0的像素)
+    PcaWhiten {
+        components: Option<usize>,
+        epsilon: f64,
+    },
+}
+
+/// 在训练集上拟合出的归一化参数
+/// 保存后在推理时加载, 保证训练和推理时对输入做完全一样的转换
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Normalizer {
+    // (v - min) / (max - min), max等于min的特征只减去min
+    MinMax {
+        min: Vec<f64>,
+        max: Vec<f64>,
+    },
+    // (v - mean) / std, std为0的特征只减去均值
+    Standardize {
+        mean: Vec<f64>,
+        std: Vec<f64>,
+    },
+    GlobalStandardize {
+        mean: f64,
+        std: f64,
+    },
+    // W * (v - mean), W每行是一个主成分除以对应标准差, 按方差从大到小排列
+    PcaWhiten {
+        mean: Vec<f64>,
+        whiten: Vec<Vec<f64>>,
+    },
+}
+
+impl Normalizer {
+    // 只用训练集拟合, 验证集和测试集用同一个Normalizer转换
+    // datas可以是 &[Mat] 的迭代器, 也可以是按需生成Mat的迭代器, 不需要一次读进内存
+    pub fn fit<T: Float, M: Borrow<Mat<T>>>(
+        kind: NormalizeKind,
+        datas: impl IntoIterator<Item = M>,
+    ) -> Result<Self> {
+        if let NormalizeKind::PcaWhiten {
+            components,
+            epsilon,
+        } = kind
+        {
+            if !(epsilon.is_finite() && epsilon > 0.) {
+                return Err(Error::InvalidParameter(format!(
+                    "PCA whitening epsilon must be positive, got {}",
+                    epsilon
+                )));
+            }
+            if components == Some(0) {
+                return Err(Error::InvalidParameter(
+                    "PCA whitening needs at least one component".into(),
+                ));
+            }
+        }
+        let stats = Stats::collect(datas, matches!(kind, NormalizeKind::PcaWhiten { .. }))?;
+        let n = stats.count as f64;
+        let mean = stats.mean;
+        Ok(match kind {
+            NormalizeKind::MinMax => Normalizer::MinMax {
+                min: stats.min,
+                max: stats.max,
+            },
+            NormalizeKind::Standardize => {
+                let std = stats.m2.iter().map(|m2| (m2 / n).sqrt()).collect();
+                Normalizer::Standardize { mean, std }
+            }
+            NormalizeKind::GlobalStandardize => {
+                // 合并各个特征的统计量: 总离差平方和 = 各特征的离差平方和 + 各特征均值相对总均值的部分
+                let total = n * mean.len() as f64;
+                let m = mean.iter().sum::<f64>() / mean.len().max(1) as f64;
+                let m2 = stats.m2.iter().sum::<f64>()
+                    + n * mean.iter().map(|v| (v - m) * (v - m)).sum::<f64>();
+                Normalizer::GlobalStandardize {
+                    mean: m,
+                    std: (m2 / total).sqrt(),
+                }
+            }
+            NormalizeKind::PcaWhiten {
+                components,
+                epsilon,
+            } => {
+                let cov = stats.cov.unwrap().scatter / n;
+                let (values, vectors) = symmetric_eigen(cov);
+                let k = components.unwrap_or(mean.len()).min(mean.len());
+                let whiten = (0..k)
+                    .map(|i| {
+                        let s = (values[i].max(0.) + epsilon).sqrt();
+                        vectors.column(i).iter().map(|v| v / s).collect()
+                    })
+                    .collect();
+                Normalizer::PcaWhiten { mean, whiten }
+            }
+        })
+    }
+
+    // 输入的特征个数, GlobalStandardize没有限制, 返回None
+    pub fn input_size(&self) -> Option<usize> {
+        match self {
+            Normalizer::MinMax { min, .. } => Some(min.len()),
+            Normalizer::Standardize { mean, .. } => Some(mean.len()),
+            Normalizer::GlobalStandardize { .. } => None,
+            Normalizer::PcaWhiten { mean, .. } => Some(mean.len()),
+        }
+    }
+
+    // 输出的特征个数, 只有PCA可能比输入少
+    pub fn output_size(&self) -> Option<usize> {
+        match self {
+            Normalizer::PcaWhiten { whiten, .. } => Some(whiten.len()),
+            _ => self.input_size(),
+        }
+    }
+
+    // data是n行1列的样本
+    pub fn transform<T: Float>(&self, data: &Mat<T>) -> Result<Mat<T>> {
+        if let Some(n) = self.input_size() {
+            if data.dim() != (n, 1) {
+                return Err(Error::FeatureMismatch {
+                    expected: n,
+                    actual: data.dim(),
+                });
+            }
+        }
+        let v = data.iter().map(|v| v.to_f64().unwrap());
+        let out: Vec<f64> = match self {
+            Normalizer::MinMax { min, max } => v
+                .zip(min.iter().zip(max))
+                .map(|(v, (lo, hi))| (v - lo) / nonzero(hi - lo))
+                .collect(),
+            Normalizer::Standardize { mean, std } => v
+                .zip(mean.iter().zip(std))
+                .map(|(v, (m, s))| (v - m) / nonzero(*s))
+                .collect(),
+            Normalizer::GlobalStandardize { mean, std } => {
+                v.map(|v| (v - mean) / nonzero(*std)).collect()
+            }
+            Normalizer::PcaWhiten { mean, whiten } => {
+                let centered: Vec<f64> = v.zip(mean).map(|(v, m)| v - m).collect();
+                whiten
+                    .iter()
+                    .map(|w| w.iter().zip(&centered).map(|(w, c)| w * c).sum())
+                    .collect()
+            }
+        };
+        let n = out.len();
+        Ok(Mat::from_shape_vec((n, 1), out.into_iter().map(T::of).collect()).unwrap())
+    }
+
+    pub fn transform_all<T: Float>(&self, datas: &[Mat<T>]) -> Result<Vec<Mat<T>>> {
+        datas.iter().map(|d| self.transform(d)).collect()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// 包装一个SampleSource, 取样本时用Normalizer转换输入, 期望结果不变
+/// 和Augmented一起用时放在外层, 先增强再归一化
+pub struct Normalized<S> {
+    source: S,
+    normalizer: Normalizer,
+}
+
+impl<S> Normalized<S> {
+    pub fn new(source: S, normalizer: Normalizer) -> Self {
+        Normalized { source, normalizer }
+    }
+
+    pub fn normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
+}
+
+impl<T: Float, S: SampleSource<T>> SampleSource<T> for Normalized<S> {
+    fn len(&self) -> usize {
+        self.source.len()
+    }
+    // 样本的形状和拟合时不一致时返回Err
+    fn get(&mut self, i: usize) -> Result<(Mat<T>, Mat<T>)> {
+        let (data, label) = self.source.get(i)?;
+        let data = self.normalizer.transform(&data)?;
+        Ok((data, label))
+    }
+    fn start_epoch(&mut self, epoch: usize) {
+        self.source.start_epoch(epoch);
+    }
+}
+
+// 常数特征不缩放
+fn nonzero(v: f64) -> f64 {
+    if v.abs() < 1e-12 {
+        1.
+    } else {
+        v
+    }
+}
+
+// 拟合需要的统计量, 一次遍历得到
+// 均值和方差用Welford算法累加, 避免 E[x^2] - E[x]^2 在均值远大于标准差时的抵消误差
+struct Stats {
+    count: usize,
+    mean: Vec<f64>,
+    // 每个特征的离差平方和
+    m2: Vec<f64>,
+    min: Vec<f64>,
+    max: Vec<f64>,
+    // 只有PCA需要
+    cov: Option<Scatter>,
+}
+
+// 已合并的样本个数, 均值和离差矩阵 Σ(x - mean)(x - mean)^T
+struct Scatter {
+    count: usize,
+    mean: Array1<f64>,
+    scatter: Array2<f64>,
+}
+
+impl Stats {
+    fn collect<T: Float, M: Borrow<Mat<T>>>(
+        datas: impl IntoIterator<Item = M>,
+        with_cov: bool,
+    ) -> Result<Self> {
+        // 攒够一批再用矩阵乘法累加协方差
+        const CHUNK: usize = 256;
+        let mut stats: Option<Stats> = None;
+        let mut chunk: Vec<f64> = vec![];
+        for (i, data) in datas.into_iter().enumerate() {
+            let data = data.borrow();
+            let n = data.len();
+            let s = stats.get_or_insert_with(|| Stats {
+                count: 0,
+                mean: vec![0.; n],
+                m2: vec![0.; n],
+                min: vec![f64::INFINITY; n],
+                max: vec![f64::NEG_INFINITY; n],
+                cov: with_cov.then(|| Scatter {
+                    count: 0,
+                    mean: Array1::zeros(n),
+                    scatter: Array2::zeros((n, n)),
+                }),
+            });
+            if data.dim() != (s.mean.len(), 1) {
+                return Err(Error::FeatureMismatch {
+                    expected: s.mean.len(),
+                    actual: data.dim(),
+                });
+            }
+            s.count += 1;
+            let count = s.count as f64;
+            for (j, v) in data.iter().enumerate() {
+                let v = v.to_f64().unwrap();
+                let d = v - s.mean[j];
+                s.mean[j] += d / count;
+                s.m2[j] += d * (v - s.mean[j]);
+                s.min[j] = s.min[j].min(v);
+                s.max[j] = s.max[j].max(v);
+            }
+            if with_cov {
+                chunk.extend(data.iter().map(|v| v.to_f64().unwrap()));
+                if (i + 1) % CHUNK == 0 {
+                    s.add_cov(&mut chunk);
+                }
+            }
+        }
+        let mut stats = stats.ok_or(Error::EmptyBatch)?;
+        if with_cov {
+            stats.add_cov(&mut chunk);
+        }
+        Ok(stats)
+    }
+
+    // 先对这一批样本减去它们自己的均值再累加, 然后和之前的结果合并 (Chan等人的并行算法)
+    fn add_cov(&mut self, chunk: &mut Vec<f64>) {
+        let n = self.mean.len();
+        if chunk.is_empty() || n == 0 {
+            return;
+        }
+        let mut x = Array2::from_shape_vec((chunk.len() / n, n), std::mem::take(chunk)).unwrap();
+        let nb = x.nrows();
+        let mean_b = x.mean_axis(Axis(0)).unwrap();
+        x -= &mean_b;
+        let c = self.cov.as_mut().unwrap();
+        let (na, total) = (c.count as f64, (c.count + nb) as f64);
+        let delta = &mean_b - &c.mean;
+        let outer = delta
+            .view()
+            .insert_axis(Axis(1))
+            .dot(&delta.view().insert_axis(Axis(0)));
+        c.scatter += &x.t().dot(&x);
+        c.scatter.scaled_add(na * nb as f64 / total, &outer);
+        c.mean.scaled_add(nb as f64 / total, &delta);
+        c.count += nb;
+    }
+}
+
+// 对称矩阵的特征分解 (Jacobi方法), 返回按从大到小排列的特征值和对应的特征向量(列)
+fn symmetric_eigen(mut a: Array2<f64>) -> (Vec<f64>, Array2<f64>) {
+    let n = a.nrows();
+    let mut v = Array2::<f64>::eye(n);
+    let total: f64 = a.iter().map(|x| x * x).sum();
+    for _ in 0..100 {
+        let off: f64 = a
+            .indexed_iter()
+            .filter(|((i, j), _)| i != j)
+            .map(|(_, x)| x * x)
+            .sum();
+        if off <= total * 1e-24 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[(p, q)];
+                if apq == 0. {
+                    continue;
+                }
+                // 选择旋转角度使 a[p][q] 变为0
+                let theta = (a[(q, q)] - a[(p, p)]) / (2. * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let (akp, akq) = (a[(k, p)], a[(k, q)]);
+                    a[(k, p)] = c * akp - s * akq;
+                    a[(k, q)] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[(p, k)], a[(q, k)]);
+                    a[(p, k)] = c * apk - s * aqk;
+                    a[(q, k)] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[(k, p)], v[(k, q)]);
+                    v[(k, p)] = c * vkp - s * vkq;
+                    v[(k, q)] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|i, j| a[(*j, *j)].total_cmp(&a[(*i, *i)]));
+    let values = order.iter().map(|i| a[(*i, *i)]).collect();
+    let vectors = v.select(Axis(1), &order);
+    (values, vectors)
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{train::SampleSource, Error, Mat};
+
+    use super::{NormalizeKind, Normalized, Normalizer};
+
+    #[test]
+    fn test() {
+        let datas: Vec<Mat<f64>> = vec![
+            array![[0.], [10.], [5.]],
+            array![[2.], [20.], [5.]],
+            array![[4.], [30.], [5.]],
+        ];
+
+        let n = Normalizer::fit(NormalizeKind::MinMax, &datas).unwrap();
+        assert_eq!(n.transform(&datas[1]).unwrap(), array![[0.5], [0.5], [0.]]);
+
+        let n = Normalizer::fit(NormalizeKind::Standardize, &datas).unwrap();
+        let out = n.transform_all(&datas).unwrap();
+        for j in 0..2 {
+            let col: Vec<f64> = out.iter().map(|m| m[(j, 0)]).collect();
+            assert!(col.iter().sum::<f64>().abs() < 1e-9);
+            assert!((col.iter().map(|v| v * v).sum::<f64>() / 3. - 1.).abs() < 1e-9);
+        }
+        assert_eq!(out[0][(2, 0)], 0.);
+
+        // MNIST像素的全局均值和标准差, 转换后可以用在不同长度的样本上
+        let n = Normalizer::fit(NormalizeKind::GlobalStandardize, &datas).unwrap();
+        assert_eq!(n.input_size(), None);
+        let out: Mat<f64> = n.transform(&array![[9.]]).unwrap();
+        assert!(out[(0, 0)].abs() < 1e-9);
+
+        // 训练时保存, 推理时加载后结果一样
+        let path = std::env::temp_dir().join(format!("hello-nn-norm-{}.json", std::process::id()));
+        n.save(&path).unwrap();
+        assert_eq!(Normalizer::load(&path).unwrap(), n);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            Normalizer::fit::<f64, &Mat<f64>>(NormalizeKind::MinMax, &[]),
+            Err(Error::EmptyBatch)
+        );
+        let n = Normalizer::fit(NormalizeKind::MinMax, &datas).unwrap();
+        assert_eq!(
+            n.transform(&array![[1.]]),
+            Err(Error::FeatureMismatch {
+                expected: 3,
+                actual: (1, 1)
+            })
+        );
+
+        let mut source = Normalized::new((&datas[..], &datas[..]), n);
+        let (data, label) = source.get(2).unwrap();
+        assert_eq!(data, array![[1.], [1.], [0.]]);
+        assert_eq!(label, datas[2]);
+
+        // 形状不一致的样本返回Err, 不会panic
+        let bad = [array![[1.], [2.]]];
+        let mut source = Normalized::new((&bad[..], &bad[..]), source.normalizer().clone());
+        assert!(source.get(0).is_err());
+    }
+
+    #[test]
+    fn test_pca() {
+        // 两个特征强相关, 白化后协方差是单位矩阵
+        let datas: Vec<Mat<f64>> = (0..50)
+            .map(|i| {
+                let x = (i as f64 * 0.7).sin() * 3.;
+                let y = (i as f64 * 1.3).cos();
+                array![[x + y], [x - y + 1.], [2. * x]]
+            })
+            .collect();
+        let kind = NormalizeKind::PcaWhiten {
+            components: Some(2),
+            epsilon: 1e-9,
+        };
+        let n = Normalizer::fit(kind, &datas).unwrap();
+        assert_eq!((n.input_size(), n.output_size()), (Some(3), Some(2)));
+
+        // epsilon为0时常数特征的方向会除以0, components为0时没有输出
+        for kind in [
+            NormalizeKind::PcaWhiten {
+                components: None,
+                epsilon: 0.,
+            },
+            NormalizeKind::PcaWhiten {
+                components: None,
+                epsilon: f64::NAN,
+            },
+            NormalizeKind::PcaWhiten {
+                components: Some(0),
+                epsilon: 1e-5,
+            },
+        ] {
+            assert!(matches!(
+                Normalizer::fit(kind, &datas),
+                Err(Error::InvalidParameter(_))
+            ));
+        }
+        let out = n.transform_all(&datas).unwrap();
+        for a in 0..2 {
+            for b in 0..2 {
+                let cov = out.iter().map(|m| m[(a, 0)] * m[(b, 0)]).sum::<f64>() / 50.;
+                let expected = if a == b { 1. } else { 0. };
+                assert!((cov - expected).abs() < 1e-6, "{} {} {}", a, b, cov);
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_offset() {
+        // 均值远大于标准差时 E[x^2] - E[x]^2 会完全抵消, 样本数超过一批以测试协方差的合并
+        let datas: Vec<Mat<f64>> = (0..600)
+            .map(|i| {
+                let x = (i % 3) as f64 - 1.;
+                let y = (i % 2) as f64 * 2. - 1.;
+                array![[1e9 + x], [-1e9 + y]]
+            })
+            .collect();
+        let n = Normalizer::fit(NormalizeKind::Standardize, &datas).unwrap();
+        let Normalizer::Standardize { mean, std } = &n else {
+            panic!("{:?}", n)
+        };
+        assert!((mean[0] - 1e9).abs() < 1e-3);
+        assert!((std[0] - (2f64 / 3.).sqrt()).abs() < 1e-6, "{:?}", std);
+        assert!((std[1] - 1.).abs() < 1e-6, "{:?}", std);
+
+        let kind = NormalizeKind::PcaWhiten {
+            components: None,
+            epsilon: 1e-9,
+        };
+        let n = Normalizer::fit(kind, &datas).unwrap();
+        let out = n.transform_all(&datas).unwrap();
+        for a in 0..2 {
+            let var = out.iter().map(|m| m[(a, 0)] * m[(a, 0)]).sum::<f64>() / 600.;
+            assert!((var - 1.).abs() < 1e-6, "{} {}", a, var);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::BTreeMap;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::{Error, Result};
+
+/// 划分出的样本个数, 按比例或固定个数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitSize {
+    Fraction(f64),
+    Count(usize),
+}
+
+impl SplitSize {
+    // n个样本中应该划分出的个数, 比例四舍五入
+    pub fn of(self, n: usize) -> usize {
+        match self {
+            SplitSize::Fraction(f) => (f * n as f64).round() as usize,
+            SplitSize::Count(c) => c,
+        }
+    }
+}
+
+/// 划分参数
+#[derive(Debug, Clone)]
+pub struct SplitConfig {
+    pub validation: SplitSize,
+    pub test: SplitSize,
+    // 按类别分层, 每个部分中各类别的比例和整体一致
+    pub stratify: bool,
+    pub seed: u64,
+}
+
+impl Default for SplitConfig {
+    // 10%作为验证集, 不划分测试集
+    fn default() -> Self {
+        SplitConfig {
+            validation: SplitSize::Fraction(0.1),
+            test: SplitSize::Count(0),
+            stratify: true,
+            seed: 0,
+        }
+    }
+}
+
+/// 划分结果, 都是样本的下标, 按从小到大排列
+/// 只返回下标, 可以用于切片, 也可以用于按需读取的数据集
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Split {
+    pub train: Vec<usize>,
+    pub validation: Vec<usize>,
+    pub test: Vec<usize>,
+}
+
+// 按config把样本划分为 训练/验证/测试 三部分, labels是每个样本的类别
+// 验证集和测试集的个数之和超过样本数时返回Err
+pub fn split<L: Ord>(labels: &[L], config: &SplitConfig) -> Result<Split> {
+    let n = labels.len();
+    let (n_val, n_test) = (config.validation.of(n), config.test.of(n));
+    if n_val.saturating_add(n_test) > n {
+        return Err(Error::InvalidParameter(format!(
+            "cannot split {} validation and {} test samples from {} samples",
+            n_val, n_test, n
+        )));
+    }
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut groups = if config.stratify {
+        group_by_label(labels)
+    } else {
+        vec![(0..n).collect()]
+    };
+    let sizes: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+    let val_counts = allocate(n_val, &sizes);
+    let rest: Vec<usize> = sizes.iter().zip(&val_counts).map(|(s, v)| s - v).collect();
+    let test_counts = allocate(n_test, &rest);
+
+    let mut result = Split::default();
+    for ((group, v), t) in groups.iter_mut().zip(val_counts).zip(test_counts) {
+        group.shuffle(&mut rng);
+        result.validation.extend_from_slice(&group[..v]);
+        result.test.extend_from_slice(&group[v..v + t]);
+        result.train.extend_from_slice(&group[v + t..]);
+    }
+    result.train.sort_unstable();
+    result.validation.sort_unstable();
+    result.test.sort_unstable();
+    Ok(result)
+}
+
+// K折交叉验证的划分, 第i个Split的验证集是第i折, 训练集是其余各折, 测试集为空
+// 每个样本恰好在一折的验证集中; 分层时各类别的样本轮流分到各折
+// k小于2时训练集为空, k小于2或大于样本数时返回Err
+pub fn k_fold<L: Ord>(labels: &[L], k: usize, stratify: bool, seed: u64) -> Result<Vec<Split>> {
+    if k < 2 || k > labels.len() {
+        return Err(Error::InvalidParameter(format!(
+            "cannot split {} samples into {} folds",
+            labels.len(),
+            k
+        )));
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let groups = if stratify {
+        group_by_label(labels)
+    } else {
+        vec![(0..labels.len()).collect()]
+    };
+    let mut folds = vec![vec![]; k];
+    // 接着上一个类别的位置继续分, 各折大小最多差1
+    let mut next = 0;
+    for mut group in groups {
+        group.shuffle(&mut rng);
+        for i in group {
+            folds[next % k].push(i);
+            next += 1;
+        }
+    }
+    Ok((0..k)
+        .map(|f| {
+            let mut validation = folds[f].clone();
+            validation.sort_unstable();
+            let mut train: Vec<usize> = folds
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != f)
+                .flat_map(|(_, fold)| fold.iter().copied())
+                .collect();
+            train.sort_unstable();
+            Split {
+                train,
+                validation,
+                test: vec![],
+            }
+        })
+        .collect())
+}
+
+// 按类别比例抽取count个样本, 返回排好序的下标
+pub fn stratified_subset<L: Ord>(labels: &[L], count: usize, seed: u64) -> Vec<usize> {
+    let mut groups = group_by_label(labels);
+    let sizes: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+    let counts = allocate(count.min(labels.len()), &sizes);
+    take_from_groups(&mut groups, &counts, seed)
+}
+
+// 每个类别抽取同样多的样本, 共count个, 比如1000张MNIST图片每个数字100张
+// 样本不够的类别全部选中, 剩下的名额分给其他类别
+pub fn balanced_subset<L: Ord>(labels: &[L], count: usize, seed: u64) -> Vec<usize> {
+    let mut groups = group_by_label(labels);
+    let mut counts = vec![0; groups.len()];
+    let mut left = count.min(labels.len());
+    while left > 0 {
+        for (c, g) in counts.iter_mut().zip(&groups) {
+            if left > 0 && *c < g.len() {
+                *c += 1;
+                left -= 1;
+            }
+        }
+    }
+    take_from_groups(&mut groups, &counts, seed)
+}
+
+// 按下标取出元素, 比如 select(&train_images, &split.validation)
+pub fn select<T: Clone>(items: &[T], indices: &[usize]) -> Vec<T> {
+    indices.iter().map(|i| items[*i].clone()).collect()
+}
+
+// 每个类别的样本下标, 按类别排序
+fn group_by_label<L: Ord>(labels: &[L]) -> Vec<Vec<usize>> {
+    let mut groups: BTreeMap<&L, Vec<usize>> = BTreeMap::new();
+    for (i, l) in labels.iter().enumerate() {
+        groups.entry(l).or_default().push(i);
+    }
+    groups.into_values().collect()
+}
+
+fn take_from_groups(groups: &mut [Vec<usize>], counts: &[usize], seed: u64) -> Vec<usize> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut result = vec![];
+    for (group, c) in groups.iter_mut().zip(counts) {
+        group.shuffle(&mut rng);
+        result.extend_from_slice(&group[..*c]);
+    }
+    result.sort_unstable();
+    result
+}
+
+// 把total个名额按sizes的比例分配, 取整后余下的名额给余数最大的组 (最大余数法)
+// 总数不超过sizes之和时, 每组分到的不超过自身大小
+fn allocate(total: usize, sizes: &[usize]) -> Vec<usize> {
+    let sum: usize = sizes.iter().sum();
+    if sum == 0 {
+        return vec![0; sizes.len()];
+    }
+    // total * size / sum 的整数部分和余数
+    let mut counts: Vec<usize> = sizes.iter().map(|s| total * s / sum).collect();
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|i| std::cmp::Reverse(total * sizes[*i] % sum));
+    // 余下的名额少于余数大于0的组数, 这些组加1后不会超过自身大小
+    let left = total - counts.iter().sum::<usize>();
+    for i in order.into_iter().take(left) {
+        counts[i] += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        balanced_subset, k_fold, select, split, stratified_subset, SplitConfig, SplitSize,
+    };
+
+    #[test]
+    fn test() {
+        // 类别0有80个, 类别1有20个
+        let labels: Vec<u8> = (0..100).map(|i| (i % 5 == 0) as u8).collect();
+        let config = SplitConfig {
+            validation: SplitSize::Fraction(0.1),
+            test: SplitSize::Count(20),
+            stratify: true,
+            seed: 1,
+        };
+        let s = split(&labels, &config).unwrap();
+        assert_eq!(
+            (s.train.len(), s.validation.len(), s.test.len()),
+            (70, 10, 20)
+        );
+        let count = |idx: &[usize]| idx.iter().filter(|i| labels[**i] == 1).count();
+        assert_eq!(count(&s.validation), 2);
+        assert_eq!(count(&s.test), 4);
+        let mut all = [s.train.clone(), s.validation.clone(), s.test.clone()].concat();
+        all.sort();
+        assert_eq!(all, (0..100).collect::<Vec<_>>());
+
+        // 同一个种子结果一样
+        assert_eq!(split(&labels, &config).unwrap(), s);
+        let other = split(&labels, &SplitConfig { seed: 2, ..config }).unwrap();
+        assert_ne!(other.validation, s.validation);
+
+        let s = split(
+            &labels,
+            &SplitConfig {
+                stratify: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!((s.train.len(), s.validation.len()), (90, 10));
+        assert!(s.test.is_empty());
+
+        let sub = stratified_subset(&labels, 10, 0);
+        assert_eq!((sub.len(), count(&sub)), (10, 2));
+        let sub = balanced_subset(&labels, 30, 0);
+        assert_eq!((sub.len(), count(&sub)), (30, 15));
+        // 类别1只有20个, 剩下的名额给类别0
+        let sub = balanced_subset(&labels, 50, 0);
+        assert_eq!((sub.len(), count(&sub)), (50, 20));
+
+        assert_eq!(select(&['a', 'b', 'c'], &[2, 0]), vec!['c', 'a']);
+
+        // 划分不出来时返回Err
+        let too_many = SplitConfig {
+            validation: SplitSize::Count(90),
+            test: SplitSize::Fraction(0.2),
+            ..Default::default()
+        };
+        assert!(split(&labels, &too_many).is_err());
+        assert!(k_fold(&labels, 0, true, 0).is_err());
+        assert!(k_fold(&labels, 1, true, 0).is_err());
+        assert!(k_fold(&labels[..2], 3, false, 0).is_err());
+
+        let folds = k_fold(&labels, 3, true, 0).unwrap();
+        let sizes: Vec<usize> = folds.iter().map(|f| f.validation.len()).collect();
+        assert_eq!(sizes, vec![34, 33, 33]);
+        let mut all: Vec<usize> = folds.iter().flat_map(|f| f.validation.clone()).collect();
+        all.sort();
+        assert_eq!(all, (0..100).collect::<Vec<_>>());
+        for f in &folds {
+            assert_eq!(f.train.len() + f.validation.len(), 100);
+            assert!((6..=7).contains(&count(&f.validation)));
+        }
+    }
+}
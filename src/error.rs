@@ -0,0 +1,89 @@
+use std::fmt;
+
+/// hello-nn的错误类型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // 训练或评估前没有设置损失函数
+    MissingLoss,
+    // 第layer层的输入形状不对, 形状都是(行, 列)
+    ShapeMismatch {
+        layer: usize,
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    // 第sample个样本的期望结果和网络输出的形状不一致
+    LabelShapeMismatch {
+        sample: usize,
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    // 特征个数不对, 比如样本应该是expected行1列, 或者权重每行应该有expected个值
+    // actual是实际的(行, 列), 用于Normalizer, 权重可视化等模型之外的地方, 模型的层用ShapeMismatch
+    FeatureMismatch {
+        expected: usize,
+        actual: (usize, usize),
+    },
+    // 批量中没有样本
+    EmptyBatch,
+    // 样本, 期望结果, 权重的个数不一致
+    BatchSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    // 出现NaN, layer为None时表示出现在损失值上
+    NaN {
+        layer: Option<usize>,
+    },
+    // 模型结构不合法
+    InvalidArchitecture(String),
+    // checkpoint和模型结构对不上
+    CheckpointMismatch(String),
+    // 参数不合法, 比如范围的下限大于上限
+    InvalidParameter(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingLoss => write!(f, "loss is not set, call minimize first"),
+            Error::ShapeMismatch {
+                layer,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "layer {}: expects input of shape {:?}, got {:?}",
+                layer, expected, actual
+            ),
+            Error::LabelShapeMismatch {
+                sample,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "sample {}: label shape {:?} does not match output shape {:?}",
+                sample, actual, expected
+            ),
+            Error::FeatureMismatch { expected, actual } => {
+                write!(f, "expects {} features, got shape {:?}", expected, actual)
+            }
+            Error::EmptyBatch => write!(f, "batch is empty"),
+            Error::BatchSizeMismatch { expected, actual } => write!(
+                f,
+                "batch size mismatch: expects {} items, got {}",
+                expected, actual
+            ),
+            Error::NaN { layer: Some(layer) } => {
+                write!(f, "NaN detected in output of layer {}", layer)
+            }
+            Error::NaN { layer: None } => write!(f, "NaN detected in loss"),
+            Error::InvalidArchitecture(msg) => write!(f, "invalid architecture: {}", msg),
+            Error::CheckpointMismatch(msg) => write!(f, "checkpoint mismatch: {}", msg),
+            Error::InvalidParameter(msg) => write!(f, "invalid parameter: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
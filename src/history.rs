@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+/// 一个批量训练后的记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchRecord {
+    pub epoch: usize,
+    // 本轮中第几个批量
+    pub batch: usize,
+    // 从训练开始累计的批量个数
+    pub step: usize,
+    pub loss: f64,
+    pub learning_rate: f64,
+    // 从训练开始经过的秒数
+    pub elapsed: f64,
+}
+
+/// 一轮训练后的记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpochRecord {
+    pub epoch: usize,
+    // 本轮所有批量损失的平均值
+    pub loss: f64,
+    // 验证集上的损失, 没有验证集时为None
+    pub val_loss: Option<f64>,
+    // 其他指标, 比如 val_accuracy
+    pub metrics: BTreeMap<String, f64>,
+    pub learning_rate: f64,
+    // 本轮花费的秒数
+    pub duration: f64,
+    // 从训练开始经过的秒数
+    pub elapsed: f64,
+}
+
+/// 训练历史, 记录每个批量和每一轮的损失, 指标, 学习率和耗时
+/// 可以导出为csv或json, 方便用外部工具画学习曲线
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    pub batches: Vec<BatchRecord>,
+    pub epochs: Vec<EpochRecord>,
+    // 之前已经经过的秒数, 从json恢复时用来接上耗时
+    #[serde(skip)]
+    offset: f64,
+    #[serde(skip)]
+    start: Option<Instant>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 从训练开始经过的秒数
+    pub fn elapsed(&mut self) -> f64 {
+        if self.start.is_none() {
+            self.start = Some(Instant::now());
+            self.offset = self.epochs.last().map_or(0., |e| e.elapsed);
+        }
+        self.offset + self.start.unwrap().elapsed().as_secs_f64()
+    }
+
+    // 已经完成的批量个数
+    pub fn steps(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn record_batch(&mut self, epoch: usize, batch: usize, loss: f64, learning_rate: f64) {
+        let elapsed = self.elapsed();
+        let step = self.batches.len();
+        self.batches.push(BatchRecord {
+            epoch,
+            batch,
+            step,
+            loss,
+            learning_rate,
+            elapsed,
+        });
+    }
+
+    pub fn record_epoch(&mut self, record: EpochRecord) {
+        self.epochs.push(record);
+    }
+
+    // 最后一轮的记录
+    pub fn last(&self) -> Option<&EpochRecord> {
+        self.epochs.last()
+    }
+
+    // 每一轮某个指标的值, loss 和 val_loss 也可以通过名字获取
+    pub fn metric(&self, name: &str) -> Vec<Option<f64>> {
+        self.epochs
+            .iter()
+            .map(|e| match name {
+                "loss" => Some(e.loss),
+                "val_loss" => e.val_loss,
+                "learning_rate" => Some(e.learning_rate),
+                _ => e.metrics.get(name).copied(),
+            })
+            .collect()
+    }
+
+    // 所有轮次出现过的指标名, 按名字排序
+    pub fn metric_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .epochs
+            .iter()
+            .flat_map(|e| e.metrics.keys().cloned())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    // 每一轮一行, 没有的值留空
+    pub fn write_epochs_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let names = self.metric_names();
+        write!(w, "epoch,loss,val_loss")?;
+        for name in &names {
+            write!(w, ",{}", name)?;
+        }
+        writeln!(w, ",learning_rate,duration,elapsed")?;
+        for e in &self.epochs {
+            write!(w, "{},{},{}", e.epoch, e.loss, opt(e.val_loss))?;
+            for name in &names {
+                write!(w, ",{}", opt(e.metrics.get(name).copied()))?;
+            }
+            writeln!(w, ",{},{},{}", e.learning_rate, e.duration, e.elapsed)?;
+        }
+        Ok(())
+    }
+
+    // 每个批量一行
+    pub fn write_batches_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "epoch,batch,step,loss,learning_rate,elapsed")?;
+        for b in &self.batches {
+            writeln!(
+                w,
+                "{},{},{},{},{},{}",
+                b.epoch, b.batch, b.step, b.loss, b.learning_rate, b.elapsed
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn save_epochs_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut f = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_epochs_csv(&mut f)?;
+        f.flush()
+    }
+
+    pub fn save_batches_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut f = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_batches_csv(&mut f)?;
+        f.flush()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(s: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(s)
+    }
+
+    pub fn save_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    pub fn load_json(path: impl AsRef<Path>) -> io::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Ok(Self::from_json(&s)?)
+    }
+}
+
+fn opt(v: Option<f64>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{EpochRecord, History};
+
+    #[test]
+    fn test() {
+        let mut h = History::new();
+        h.record_batch(0, 0, 1.5, 0.1);
+        h.record_batch(0, 1, 0.5, 0.1);
+        h.record_epoch(EpochRecord {
+            epoch: 0,
+            loss: 1.,
+            val_loss: None,
+            metrics: BTreeMap::new(),
+            learning_rate: 0.1,
+            duration: 2.,
+            elapsed: 2.,
+        });
+        h.record_epoch(EpochRecord {
+            epoch: 1,
+            loss: 0.5,
+            val_loss: Some(0.75),
+            metrics: BTreeMap::from([("val_accuracy".to_string(), 0.875)]),
+            learning_rate: 0.05,
+            duration: 1.5,
+            elapsed: 3.5,
+        });
+        assert_eq!(h.steps(), 2);
+        assert_eq!(h.batches[1].step, 1);
+        assert_eq!(h.metric("val_accuracy"), vec![None, Some(0.875)]);
+        assert_eq!(h.metric("loss"), vec![Some(1.), Some(0.5)]);
+
+        let mut csv = vec![];
+        h.write_epochs_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "epoch,loss,val_loss,val_accuracy,learning_rate,duration,elapsed\n\
+             0,1,,,0.1,2,2\n\
+             1,0.5,0.75,0.875,0.05,1.5,3.5\n"
+        );
+        let mut csv = vec![];
+        h.write_batches_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().nth(2).unwrap().starts_with("0,1,1,0.5,0.1,"));
+
+        let back = History::from_json(&h.to_json()).unwrap();
+        assert_eq!(back.epochs, h.epochs);
+        assert_eq!(back.batches, h.batches);
+        // 恢复后耗时接着之前的继续累计
+        let mut back = back;
+        assert!(back.elapsed() >= 3.5);
+    }
+}
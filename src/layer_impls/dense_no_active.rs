@@ -1,17 +1,17 @@
-use crate::{Layer, LayerCache, Mat, MatView};
+use crate::{Float, Layer, LayerCache, Mat, MatView};
 
 use ndarray_rand::RandomExt;
 use rand::distributions::Distribution;
 
 // 没有激活函数的全连接层
-pub struct DenseLayerNoActive {
+pub struct DenseLayerNoActive<T: Float = f32> {
     // 每个神经元与上一层所有神经元边的权重, n行j列,n是本层神经元个数,j是前一层神经元个数
-    pub w: Mat,
+    pub w: Mat<T>,
     // 每个神经元的偏置, n行1列
-    pub b: Mat,
+    pub b: Mat<T>,
 }
 
-impl DenseLayerNoActive {
+impl<T: Float> DenseLayerNoActive<T> {
     // 参数初始化为全0
     pub fn new(pre_cnt: usize, cell_cnt: usize) -> Self {
         let w = Mat::zeros((cell_cnt, pre_cnt));
@@ -19,15 +19,15 @@ impl DenseLayerNoActive {
         Self { w, b }
     }
     // 随机初始化参数
-    pub fn new_with(pre_cnt: usize, cell_cnt: usize, dist: impl Distribution<f32> + Clone) -> Self {
+    pub fn new_with(pre_cnt: usize, cell_cnt: usize, dist: impl Distribution<T> + Clone) -> Self {
         let w = Mat::random((cell_cnt, pre_cnt), dist.clone());
         let b = Mat::zeros((cell_cnt, 1));
         Self { w, b }
     }
 }
 
-impl Layer for DenseLayerNoActive {
-    fn forward(&mut self, input: &MatView, training: bool) -> (Mat, LayerCache) {
+impl<T: Float> Layer<T> for DenseLayerNoActive<T> {
+    fn forward(&mut self, input: &MatView<T>, training: bool) -> (Mat<T>, LayerCache<T>) {
         // 计算每个神经元激活值 w1*a1 + w2*a2 + ... + wn*an + b
         // 矩阵计算,一次算出结果, w的每行乘以输入的一列最后加b
         let r = self.w.dot(input) + &self.b;
@@ -41,7 +41,11 @@ impl Layer for DenseLayerNoActive {
     // 每个神经元有多(k)条入边返回的梯度是 n行k列
     // z=w*a+b 对w求导是a, 对b求导是1
     // 每个神经元看作有多条出边,链式法则后仍要累加(大多情况后一层是激活函数层,只有1条出边,但不排除其他可能)
-    fn backward(&mut self, grads: &MatView, cache_forward: &LayerCache) -> (Mat, LayerCache) {
+    fn backward(
+        &mut self,
+        grads: &MatView<T>,
+        cache_forward: &LayerCache<T>,
+    ) -> (Mat<T>, LayerCache<T>) {
         let a = cache_forward[0].view();
 
         let mut bias_grads = Mat::zeros(self.b.raw_dim());
@@ -52,7 +56,7 @@ impl Layer for DenseLayerNoActive {
             // 累加当前神经元每条出边上的偏导, grads的每行,都是前一层某个神经元和本层连线的偏导
             for g in grads.rows().into_iter() {
                 // b在这里求 链式法则相乘
-                bias_grads[(i, 0)] += g[i] * 1.;
+                bias_grads[(i, 0)] += g[i];
                 //每个神经元上都有和前一层神经元的边, 连接w和a
                 for (k, a) in a.rows().into_iter().enumerate() {
                     w_grads[(i, k)] += a[0] * g[i];
@@ -66,7 +70,7 @@ impl Layer for DenseLayerNoActive {
         (w_grads, grads_cache)
     }
 
-    fn update(&mut self, learning_rate: f32, grades: &LayerCache) {
+    fn update(&mut self, learning_rate: T, grades: &LayerCache<T>) {
         let bias_grads = grades[0].view();
         let w_grads = grades[1].view();
         // 更新偏置
@@ -78,6 +82,26 @@ impl Layer for DenseLayerNoActive {
             self.b[(i, 0)] -= learning_rate * bias_grads[(i, 0)];
         }
     }
+
+    fn input_size(&self) -> Option<usize> {
+        Some(self.w.ncols())
+    }
+
+    fn output_size(&self, _input_size: usize) -> usize {
+        self.w.nrows()
+    }
+
+    fn name(&self) -> &str {
+        "Dense"
+    }
+
+    fn params(&self) -> Vec<(&str, &Mat<T>)> {
+        vec![("b", &self.b), ("w", &self.w)]
+    }
+
+    fn params_mut(&mut self) -> Vec<(&str, &mut Mat<T>)> {
+        vec![("b", &mut self.b), ("w", &mut self.w)]
+    }
 }
 
 #[cfg(test)]
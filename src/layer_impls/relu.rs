@@ -1,4 +1,4 @@
-use crate::{Layer, LayerCache, Mat, MatView};
+use crate::{Float, Layer, LayerCache, Mat, MatView};
 
 #[derive(Debug)]
 pub struct ReLULayer {}
@@ -9,10 +9,16 @@ impl ReLULayer {
     }
 }
 
-impl Layer for ReLULayer {
+impl Default for ReLULayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Layer<T> for ReLULayer {
     // 激活函数层每个神经元只有一条入边, 只是对上层的输出做一个转换, 矩阵形状n行1列
-    fn forward(&mut self, input: &MatView, training: bool) -> (Mat, LayerCache) {
-        let out = input.map(|x| x.max(0.));
+    fn forward(&mut self, input: &MatView<T>, training: bool) -> (Mat<T>, LayerCache<T>) {
+        let out = input.map(|x| x.max(T::zero()));
         // 只有在训练时候才保存输出值，反向传播会用到
         let mut cache = vec![];
         if training {
@@ -22,12 +28,16 @@ impl Layer for ReLULayer {
     }
 
     // relu偏导 x > 0 为1 其他情况为0
-    fn backward(&mut self, grads: &MatView, cache_forward: &LayerCache) -> (Mat, LayerCache) {
+    fn backward(
+        &mut self,
+        grads: &MatView<T>,
+        cache_forward: &LayerCache<T>,
+    ) -> (Mat<T>, LayerCache<T>) {
         // 本层input的值
         let a = cache_forward[0].view();
 
         // 当前每个神经元上的偏导，n个神经元，每个神经元只有一条出边
-        let mut r = Mat::from_shape_fn((a.len(), 1), |(_, _)| 0.);
+        let mut r = Mat::from_shape_fn((a.len(), 1), |(_, _)| T::zero());
 
         // 对每个神经元求梯度
         for (i, input) in a.iter().enumerate() {
@@ -35,7 +45,12 @@ impl Layer for ReLULayer {
             for g in grads.rows().into_iter() {
                 // 链式法则,与输入偏导相乘
                 // 当前神经元为 i, 所以g也取每行第i个
-                r[(i, 0)] += g[i] * (if *input > 0. { 1. } else { 0. });
+                r[(i, 0)] += g[i]
+                    * (if *input > T::zero() {
+                        T::one()
+                    } else {
+                        T::zero()
+                    });
             }
         }
 
@@ -45,9 +60,13 @@ impl Layer for ReLULayer {
         (r, vec![])
     }
 
-    fn update(&mut self, _learning_rate: f32, _gradss: &LayerCache) {
+    fn update(&mut self, _learning_rate: T, _gradss: &LayerCache<T>) {
         //不需要做任何事情
     }
+
+    fn name(&self) -> &str {
+        "ReLU"
+    }
 }
 
 #[cfg(test)]
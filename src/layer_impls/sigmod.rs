@@ -1,4 +1,4 @@
-use crate::{sigmod, Layer, LayerCache, Mat, MatView};
+use crate::{sigmod, Float, Layer, LayerCache, Mat, MatView};
 // 使用激活函数sigmod的层
 #[derive(Debug)]
 pub struct SigmodLayer {}
@@ -9,9 +9,15 @@ impl SigmodLayer {
     }
 }
 
-impl Layer for SigmodLayer {
+impl Default for SigmodLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Layer<T> for SigmodLayer {
     // 激活函数层每个神经元只有一条入边, 只是对上层的输出做一个转换, 矩阵形状n行1列
-    fn forward(&mut self, input: &MatView, training: bool) -> (Mat, LayerCache) {
+    fn forward(&mut self, input: &MatView<T>, training: bool) -> (Mat<T>, LayerCache<T>) {
         let out = input.map(|x| sigmod(*x));
         // 只有在训练时候才保存输出值，反向传播会用到
         let mut cache = vec![];
@@ -24,14 +30,16 @@ impl Layer for SigmodLayer {
     // 激活函数层反向传播, 对sigmod(x)求导即可, 每个神经元只有一条入边,返回的梯度是 1行n列
     // simod(x)求导是 sigmod(x)*(1-sigmod(x))
     // 每个神经元有多条出边,链式法则后要累加结果
-    fn backward(&mut self, grads: &MatView, cache_forward: &LayerCache) -> (Mat, LayerCache) {
+    fn backward(
+        &mut self,
+        grads: &MatView<T>,
+        cache_forward: &LayerCache<T>,
+    ) -> (Mat<T>, LayerCache<T>) {
         // sigmod(x)的值
         let a = cache_forward[0].view();
 
-        println!("SIGMOD A:{}", a);
-
         // 当前每个神经元上的偏导，n个神经元，每个神经元只有一条出边
-        let mut r = Mat::from_shape_fn((a.len(), 1), |(_, _)| 0.);
+        let mut r = Mat::from_shape_fn((a.len(), 1), |(_, _)| T::zero());
 
         // 对每个神经元求梯度
         for (i, out) in a.iter().enumerate() {
@@ -39,7 +47,7 @@ impl Layer for SigmodLayer {
             for g in grads.rows().into_iter() {
                 // 链式法则,与输入偏导相乘
                 // 当前神经元为 i, 所以g也取每行第i个
-                r[(i, 0)] += g[i] * (out * (1.0 - out));
+                r[(i, 0)] += g[i] * (*out * (T::one() - *out));
             }
         }
 
@@ -49,9 +57,13 @@ impl Layer for SigmodLayer {
         (r, vec![])
     }
 
-    fn update(&mut self, _learning_rate: f32, _gradss: &LayerCache) {
+    fn update(&mut self, _learning_rate: T, _gradss: &LayerCache<T>) {
         //不需要做任何事情
     }
+
+    fn name(&self) -> &str {
+        "Sigmod"
+    }
 }
 
 #[cfg(test)]
@@ -67,10 +79,8 @@ mod test {
 
         let mut s = SigmodLayer::new();
         let (a, f_cache) = s.forward(&array![[0.], [0.]].view(), true);
-        println!("a:\n{}", a);
         assert_eq!(a, array![[0.5], [0.5]]);
         let (g, b_cache) = s.backward(&array![[0.5, 0.5]].view(), &f_cache);
-        println!("g:\n{}", g);
         assert_eq!(g, array![[0.125, 0.125]]);
         s.update(0.1, &b_cache);
     }
@@ -1,4 +1,4 @@
-use crate::{Layer, LayerCache, Mat, MatView};
+use crate::{Float, Layer, LayerCache, Mat, MatView};
 
 #[derive(Debug)]
 pub struct SoftmaxLayer {}
@@ -8,11 +8,20 @@ impl SoftmaxLayer {
         Self {}
     }
 }
-impl Layer for SoftmaxLayer {
+
+impl Default for SoftmaxLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Layer<T> for SoftmaxLayer {
     // 输入为上层激活值，n行1列
-    fn forward(&mut self, input: &MatView, training: bool) -> (Mat, LayerCache) {
-        let sum = input.fold(0., |acc, b| acc + b.exp());
-        let out = input.map(|x| x.exp() / sum);
+    fn forward(&mut self, input: &MatView<T>, training: bool) -> (Mat<T>, LayerCache<T>) {
+        // 先减去最大值, 避免exp上溢
+        let max = input.fold(T::neg_infinity(), |m, v| m.max(*v));
+        let sum = input.fold(T::zero(), |acc, b| acc + (*b - max).exp());
+        let out = input.map(|x| (*x - max).exp() / sum);
         // 只有在训练时候才保存输出值，反向传播会用到
         let mut cache = vec![];
         if training {
@@ -21,32 +30,54 @@ impl Layer for SoftmaxLayer {
         (out, cache)
     }
 
-    // 前一层节点数量和本层节点数量一致，直接对交叉熵损失函数求导而不是对softmax求导，这样更简单
-    // 一定在最后一层
-    fn backward(&mut self, grads: &MatView, cache_forward: &LayerCache) -> (Mat, LayerCache) {
+    // 每个输出a[j]都和所有输入z[i]有关, a[j]对z[i]的偏导:
+    // i == j: a[i]*(1-a[i]), i != j: -a[i]*a[j]
+    // 链式法则累加后 L/z[i] = a[i] * (g[i] - Σ g[j]*a[j])
+    // 和交叉熵组合时 g[i] = -y[i]/a[i], 结果化简为 a[i] - y[i]
+    // 模型训练时会直接使用损失函数的合并形式(Loss::calc_with_softmax), 不经过这里
+    fn backward(
+        &mut self,
+        grads: &MatView<T>,
+        cache_forward: &LayerCache<T>,
+    ) -> (Mat<T>, LayerCache<T>) {
         // 本层输出值 a[i] = softmax[i]
         let a = cache_forward[0].view();
 
-        // 当前每个神经元上的偏导，n个神经元，每个神经元只有一条出边
-        // L/z = pi - yi
-        let mut r = &a.view() - &grads.view();
+        // 先累加每个神经元所有出边上的偏导
+        let mut g: Mat<T> = Mat::zeros((a.len(), 1));
+        for row in grads.rows().into_iter() {
+            for (i, v) in row.iter().enumerate() {
+                g[(i, 0)] += *v;
+            }
+        }
 
-        // 直接算出了上层输出对交叉熵的偏导，所以矩阵形状应该是 (1,n)
-        r = r.t().to_owned();
+        let dot = a
+            .iter()
+            .zip(g.iter())
+            .fold(T::zero(), |acc, (a, g)| acc + *a * *g);
+        let r = Mat::from_shape_fn((1, a.len()), |(_, i)| a[(i, 0)] * (g[(i, 0)] - dot));
 
         (r, vec![])
     }
 
-    fn update(&mut self, _learning_rate: f32, _gradss: &LayerCache) {
+    fn update(&mut self, _learning_rate: T, _gradss: &LayerCache<T>) {
         //不需要做任何事情
     }
+
+    fn name(&self) -> &str {
+        "Softmax"
+    }
+
+    fn is_softmax(&self) -> bool {
+        true
+    }
 }
 
 #[cfg(test)]
 mod test {
     use ndarray::array;
 
-    use crate::Layer;
+    use crate::{loss_impls::CrossEntropy, Layer, Loss, Mat};
 
     use super::SoftmaxLayer;
 
@@ -55,7 +86,10 @@ mod test {
         let mut l = SoftmaxLayer::new();
         let (out, cache) = l.forward(&array![[2.], [3.], [5.]].view(), true);
         assert_eq!(out, array![[0.042010065], [0.1141952], [0.8437947]]);
-        let (g, _) = l.backward(&array![[0.0], [1.0], [0.0]].view(), &cache);
-        assert_eq!(g, array![[0.042010065, -0.8858048, 0.8437947]]);
+        let label = array![[0.0], [1.0], [0.0]];
+        let (_, grads) = CrossEntropy::new().calc(&out.view(), &label.view());
+        let (g, _) = l.backward(&grads.view(), &cache);
+        let want: Mat = array![[0.042010065, -0.8858048, 0.8437947]];
+        assert!(g.iter().zip(want.iter()).all(|(a, b)| (a - b).abs() < 1e-6));
     }
 }
@@ -1,155 +1,554 @@
+pub mod builder;
+pub mod checkpoint;
+pub mod cross_validation;
+pub mod dataset;
+mod error;
+pub mod history;
 pub mod layer_impls;
 pub mod loss_impls;
+pub mod metrics;
+pub mod search;
+mod summary;
+pub mod tensorboard;
+pub mod train;
 pub mod util;
+pub mod visualize;
 
-use ndarray::{Array2, ArrayView2, ArrayViewMut2};
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
 
-use crate::util::{calc_all_grads_avg};
+use ndarray::{Array2, ArrayView2, ArrayViewMut2, LinalgScalar, ScalarOperand};
+use num_traits::{FromPrimitive, NumAssign};
 
-pub struct NeuralNetworkModel {
-    pub layers: Vec<Box<dyn Layer>>,
-    pub loss: Option<Box<dyn Loss>>,
+pub use crate::error::{Error, Result};
+use crate::util::calc_all_grads_sum;
+
+pub struct NeuralNetworkModel<T: Float = f32> {
+    pub layers: Vec<Box<dyn Layer<T>>>,
+    pub loss: Option<Box<dyn Loss<T>>>,
+    // 损失函数对一个批量的归约方式
+    pub reduction: Reduction,
+    // 输入神经元个数, 通过builder构建时会记录
+    pub input_size: Option<usize>,
+    // 最近一次fit时每层参数的梯度, 格式与backward返回的一致
+    pub last_grads: Vec<LayerCache<T>>,
 }
-impl NeuralNetworkModel {
+impl<T: Float> NeuralNetworkModel<T> {
     pub fn new() -> Self {
         NeuralNetworkModel {
             layers: vec![],
             loss: None,
+            reduction: Reduction::Mean,
+            input_size: None,
+            last_grads: vec![],
         }
     }
-    pub fn minimize(&mut self, loss: impl Loss + 'static) {
+    pub fn minimize(&mut self, loss: impl Loss<T> + 'static) {
         self.loss = Some(Box::new(loss));
     }
-    pub fn push_layer<L: Layer + 'static>(&mut self, layer: L) {
+    pub fn minimize_with(&mut self, loss: impl Loss<T> + 'static, reduction: Reduction) {
+        self.minimize(loss);
+        self.reduction = reduction;
+    }
+    pub fn push_layer<L: Layer<T> + 'static>(&mut self, layer: L) {
         self.layers.push(Box::new(layer));
     }
-    pub fn predict(&mut self, data: &MatView) -> Mat {
+    pub fn predict(&mut self, data: &MatView<T>) -> Result<Mat<T>> {
+        self.forward(data, None)
+    }
+
+    // 正向传播, 检查每层输入的形状和输出是否有NaN
+    // caches不为None时是训练模式, 保存每层的中间结果
+    fn forward(
+        &mut self,
+        data: &MatView<T>,
+        mut caches: Option<&mut Vec<LayerCache<T>>>,
+    ) -> Result<Mat<T>> {
         let mut pre = data.to_owned();
-        for layer in self.layers.iter_mut() {
-            let (a, _) = layer.forward(&pre.view(), false);
+        for (j, layer) in self.layers.iter_mut().enumerate() {
+            let rows = layer.input_size().unwrap_or(pre.nrows());
+            if pre.dim() != (rows, 1) {
+                return Err(Error::ShapeMismatch {
+                    layer: j,
+                    expected: (rows, 1),
+                    actual: pre.dim(),
+                });
+            }
+            let (a, f_cache) = layer.forward(&pre.view(), caches.is_some());
+            if a.iter().any(|v| v.is_nan()) {
+                return Err(Error::NaN { layer: Some(j) });
+            }
+            if let Some(caches) = caches.as_mut() {
+                caches.push(f_cache);
+            }
             pre = a;
         }
-        pre
+        Ok(pre)
+    }
+
+    // 计算一批样本在当前模型上的损失, 不做反向传播
+    pub fn evaluate(&mut self, datas: &[Mat<T>], labels: &[Mat<T>]) -> Result<LossOutput<T>> {
+        Ok(self.evaluate_results(datas, labels)?.0)
+    }
+
+    // 同evaluate, 额外返回每个样本的正向传播结果, 方便计算其他指标
+    pub(crate) fn evaluate_results(
+        &mut self,
+        datas: &[Mat<T>],
+        labels: &[Mat<T>],
+    ) -> Result<(LossOutput<T>, Vec<Mat<T>>)> {
+        if self.loss.is_none() {
+            return Err(Error::MissingLoss);
+        }
+        let results = datas
+            .iter()
+            .map(|d| self.predict(&d.view()))
+            .collect::<Result<Vec<Mat<T>>>>()?;
+        let loss = self.loss.as_ref().ok_or(Error::MissingLoss)?;
+        let output = loss.compute(&results, labels, self.reduction, None)?;
+        if output.loss.is_nan() {
+            return Err(Error::NaN { layer: None });
+        }
+        Ok((output, results))
+    }
+
+    pub fn fit(&mut self, datas: &[Mat<T>], labels: &[Mat<T>], learning_rate: T) -> Result<T> {
+        self.fit_weighted(datas, labels, None, learning_rate)
     }
 
-    pub fn fit(&mut self, datas: &[Mat], labels: &[Mat], learning_rate: f32) -> f32 {
-        let loss = self.loss.as_mut().expect("remember set loss");
-        loss.reset();
-        let mut forward_cache: Vec<Vec<LayerCache>> = vec![]; // forwart_cache[i][j] 表示第i个样本的第j层缓存
-        let mut out_cache: Vec<Mat> = vec![]; // out_cache[i] 表示第i个样本的正向传播结果
+    // weights: 每个样本的权重, 为None时每个样本权重都是1
+    // 出错时不会更新任何参数
+    pub fn fit_weighted(
+        &mut self,
+        datas: &[Mat<T>],
+        labels: &[Mat<T>],
+        weights: Option<&[T]>,
+        learning_rate: T,
+    ) -> Result<T> {
+        if self.loss.is_none() {
+            return Err(Error::MissingLoss);
+        }
+        let mut forward_cache: Vec<Vec<LayerCache<T>>> = vec![]; // forwart_cache[i][j] 表示第i个样本的第j层缓存
+        let mut out_cache: Vec<Mat<T>> = vec![]; // out_cache[i] 表示第i个样本的正向传播结果
 
         let batch_size = datas.len();
-        // 对每个样本进行正向传播, 算出方差
-        for i in 0..batch_size {
-            forward_cache.push(vec![]);
-            let data = datas[i].view();
-            let label = labels[i].view();
-            let mut pre = data.to_owned();
-            for (_j, layer) in self.layers.iter_mut().enumerate() {
-                let (a, f_cache) = layer.forward(&pre.view(), true);
-                forward_cache[i].push(f_cache);
-                pre = a;
+        // 对每个样本进行正向传播
+        for data in datas.iter() {
+            let mut caches = vec![];
+            out_cache.push(self.forward(&data.view(), Some(&mut caches))?);
+            forward_cache.push(caches);
+        }
+        // 一次算出整个批量的损失和每个样本输出上的梯度, 梯度已经按归约方式和权重缩放过
+        // 最后一层是softmax且损失函数有合并形式时, 直接得到softmax输入上的梯度, 跳过softmax层
+        let loss = self.loss.as_ref().ok_or(Error::MissingLoss)?;
+        let fused = match self.layers.last() {
+            Some(last) if last.is_softmax() => {
+                loss.compute_with_softmax(&out_cache, labels, self.reduction, weights)?
             }
-            //println!("a:\n{}\nlabel:\n{}", pre, label);
-            loss.sum_loss(&pre.view(), &label.view());
-
-            //println!("diff:\n{} a2:{}", diff, suma2);
-            out_cache.push(pre);
+            _ => None,
+        };
+        let (output, end) = match fused {
+            Some(output) => (output, self.layers.len() - 1),
+            None => (
+                loss.compute(&out_cache, labels, self.reduction, weights)?,
+                self.layers.len(),
+            ),
+        };
+        if output.loss.is_nan() {
+            return Err(Error::NaN { layer: None });
         }
 
-        // 对每个样本进行反向传播，得到梯度，初始的偏导是 2(a-b)/n
+        // 对每个样本进行反向传播，得到梯度
         // 收集每个样本的全量梯度，将他们的梯度相加
         let mut grad_cache = Vec::with_capacity(batch_size);
-        for i in 0..batch_size {
-            let a = &out_cache[i].view();
-            let b = &labels[i].view();
-            let mut grads = loss.grads(a, b);
-
-            // println!("a    : {}", a.t());
-            // println!("b    : {}", b.t());
-            // println!("grads: {}", grads);
-
-            // let mut b = String::new();
-            // std::io::stdin().read_line(&mut b);
-
+        for (i, mut grads) in output.grads.into_iter().enumerate() {
             let mut cache = vec![vec![]; self.layers.len()];
-            for j in (0..self.layers.len()).rev() {
-                // println!("g:\n{}", grads);
-                // pause();
-                // println!(
-                //     "now layer {}, caceh forward:\n{:?}",
-                //     j, &forward_cache[i][j]
-                // );
+            for j in (0..end).rev() {
                 // 从后往前
                 let layer = &mut self.layers[j];
                 let (g, backwark_cache) = layer.backward(&grads.view(), &forward_cache[i][j]);
                 grads = g;
-
-                //println!("grads: {}", grads);
-                //println!("layer {} cache len:{}", j, backwark_cache.len());
                 cache[j] = backwark_cache;
             }
-
-            //println!("item {}, cache len:{}", i, cache.len());
             grad_cache.push(cache);
         }
 
-        // 把每个样本的全量梯度相加，然后取平均，作为最终调整参数的梯度
-        let cache: Vec<LayerCache> = calc_all_grads_avg(&grad_cache);
-        //println!("avg cache len:{}", cache.len());
+        // 把每个样本的全量梯度相加，作为最终调整参数的梯度
+        let cache: Vec<LayerCache<T>> = calc_all_grads_sum(&grad_cache);
         for (i, layer) in self.layers.iter_mut().enumerate() {
-            // println!("layer {} cache:", i);
-            // for v in &cache[i] {
-            //     println!("->\n{}", v);
-            // }
             layer.update(learning_rate, &cache[i]);
         }
+        self.last_grads = cache;
+
+        Ok(output.loss)
+    }
+}
 
-        //println!("loss: {}", loss);
-        return loss.loss();
+impl<T: Float> Default for NeuralNetworkModel<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-pub type Mat = Array2<f32>;
-pub type MatView<'a> = ArrayView2<'a, f32>;
-pub type MatViewMut<'a> = ArrayViewMut2<'a, f32>;
-pub type LayerCache = Vec<Mat>; // 每个layer的中间结果和梯度缓存
+/// 网络计算使用的浮点数类型, f32速度更快, f64精度更高(比如做梯度检查)
+pub trait Float:
+    num_traits::Float
+    + FromPrimitive
+    + NumAssign
+    + LinalgScalar
+    + ScalarOperand
+    + Sum
+    + Default
+    + Debug
+    + Display
+    + Send
+    + Sync
+    + 'static
+{
+    // 把f64常量转换为当前精度
+    fn of(v: f64) -> Self {
+        Self::from_f64(v).unwrap()
+    }
+}
+
+impl Float for f32 {}
+impl Float for f64 {}
+
+pub type Mat<T = f32> = Array2<T>;
+pub type MatView<'a, T = f32> = ArrayView2<'a, T>;
+pub type MatViewMut<'a, T = f32> = ArrayViewMut2<'a, T>;
+pub type LayerCache<T = f32> = Vec<Mat<T>>; // 每个layer的中间结果和梯度缓存
 
-pub trait ToLayerCache {
-    fn to_layer_cache(self) -> LayerCache;
+pub trait ToLayerCache<T> {
+    fn to_layer_cache(self) -> LayerCache<T>;
 }
 
-impl ToLayerCache for Mat {
-    fn to_layer_cache(self) -> LayerCache {
+impl<T> ToLayerCache<T> for Mat<T> {
+    fn to_layer_cache(self) -> LayerCache<T> {
         vec![self]
     }
 }
 
-pub trait Layer {
+pub trait Layer<T: Float = f32> {
     // 正向传播
     // 返回：本层输出 & 本层中间结果
-    fn forward(&mut self, input: &MatView, training: bool) -> (Mat, LayerCache);
+    fn forward(&mut self, input: &MatView<T>, training: bool) -> (Mat<T>, LayerCache<T>);
     // 反向传播
     // grads: 后面一层传递过来的梯度
     // cache_forward: 本层正向传播时的输入和激活值，内容为forward的返回
     // 返回: 本层向前一层传递的梯度 & 本层所有梯度值
-    fn backward(&mut self, grads: &MatView, cache_forward: &LayerCache) -> (Mat, LayerCache);
+    fn backward(
+        &mut self,
+        grads: &MatView<T>,
+        cache_forward: &LayerCache<T>,
+    ) -> (Mat<T>, LayerCache<T>);
     // 更新权重和偏置
     // grads: 本层调整参考的梯度, 内容格式与backward返回的一致
-    fn update(&mut self, learning_rate: f32, grads: &LayerCache);
+    fn update(&mut self, learning_rate: T, grads: &LayerCache<T>);
+    // 本层要求的输入神经元个数, None表示不限制(激活函数层)
+    fn input_size(&self) -> Option<usize> {
+        None
+    }
+    // 根据输入神经元个数推断本层输出神经元个数, 默认与输入一致
+    fn output_size(&self, input_size: usize) -> usize {
+        input_size
+    }
+    // 层的类型名
+    fn name(&self) -> &str;
+    // 本层可训练的参数和参数名, 没有参数的层返回空
+    // 顺序与backward返回的梯度一致
+    fn params(&self) -> Vec<(&str, &Mat<T>)> {
+        vec![]
+    }
+    // 同params, 用于从checkpoint恢复参数
+    fn params_mut(&mut self) -> Vec<(&str, &mut Mat<T>)> {
+        vec![]
+    }
+    // 可训练参数的个数
+    fn param_count(&self) -> usize {
+        self.params().iter().map(|(_, p)| p.len()).sum()
+    }
+    // 是否是softmax层, 模型的最后一层是softmax时可以和损失函数合并求梯度
+    fn is_softmax(&self) -> bool {
+        false
+    }
+}
+
+/// 损失函数对一个批量的归约方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reduction {
+    // 加权损失的平均值 Σ(w*l)/n
+    Mean,
+    // 加权损失的和 Σ(w*l)
+    Sum,
+    // 不归约, 每个样本的梯度只乘以自己的权重, 各自的损失值在LossOutput::losses里
+    None,
+}
+
+/// 一个批量的损失计算结果
+#[derive(Debug, Clone)]
+pub struct LossOutput<T = f32> {
+    // 归约后的损失值, 和梯度对应, Reduction::None 时是所有样本加权损失的和
+    pub loss: T,
+    // 每个样本加权后的损失值
+    pub losses: Vec<T>,
+    // 每个样本输出结果上的梯度, 1行n列, 已经按归约方式和权重缩放
+    pub grads: Vec<Mat<T>>,
 }
 
-/// 损失函数抽象
-pub trait Loss {
-    fn sum_loss(&mut self, result: &MatView, label: &MatView);
-    // 获取loss
-    fn loss(&self) -> f32;
-    fn reset(&mut self);
-    // 单个样本的梯度
-    fn grads(&mut self, result: &MatView, label: &MatView) -> Mat;
+/// 损失函数抽象, 不保存状态, 训练和评估都可以直接使用
+pub trait Loss<T: Float = f32> {
+    // 单个样本的损失值和输出结果上的梯度
+    // 输出结果 和 期望结果 都是n行1列, 返回的梯度是1行n列
+    fn calc(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>);
+
+    // 最后一层是softmax时, 单个样本的损失值和对softmax输入的梯度
+    // 不用经过softmax的雅可比矩阵, 概率下溢为0时梯度也不会消失
+    // 返回None表示没有合并的形式, 按一般的链式法则反向传播
+    fn calc_with_softmax(&self, _result: &MatView<T>, _label: &MatView<T>) -> Option<(T, Mat<T>)> {
+        None
+    }
+
+    // 计算一个批量的损失值和梯度
+    // weights: 每个样本的权重, 为None时每个样本权重都是1
+    // 批量为空, 个数或形状对不上时返回错误
+    fn compute(
+        &self,
+        results: &[Mat<T>],
+        labels: &[Mat<T>],
+        reduction: Reduction,
+        weights: Option<&[T]>,
+    ) -> Result<LossOutput<T>> {
+        check_batch(results, labels, weights)?;
+        let samples = results
+            .iter()
+            .zip(labels)
+            .map(|(result, label)| self.calc(&result.view(), &label.view()))
+            .collect();
+        Ok(reduce(samples, reduction, weights))
+    }
+
+    // 同compute, 梯度是对softmax输入的梯度, 没有合并形式时返回None
+    fn compute_with_softmax(
+        &self,
+        results: &[Mat<T>],
+        labels: &[Mat<T>],
+        reduction: Reduction,
+        weights: Option<&[T]>,
+    ) -> Result<Option<LossOutput<T>>> {
+        check_batch(results, labels, weights)?;
+        let samples: Option<Vec<_>> = results
+            .iter()
+            .zip(labels)
+            .map(|(result, label)| self.calc_with_softmax(&result.view(), &label.view()))
+            .collect();
+        Ok(samples.map(|samples| reduce(samples, reduction, weights)))
+    }
+}
+
+// 批量不为空, 输出结果, 期望结果和权重的个数一致, 每个样本的输出和期望结果形状一致
+fn check_batch<T: Float>(
+    results: &[Mat<T>],
+    labels: &[Mat<T>],
+    weights: Option<&[T]>,
+) -> Result<()> {
+    if results.is_empty() {
+        return Err(Error::EmptyBatch);
+    }
+    let len = weights.map_or(labels.len(), |w| w.len());
+    if labels.len() != results.len() || len != results.len() {
+        return Err(Error::BatchSizeMismatch {
+            expected: results.len(),
+            actual: if labels.len() != results.len() {
+                labels.len()
+            } else {
+                len
+            },
+        });
+    }
+    for (i, (result, label)) in results.iter().zip(labels).enumerate() {
+        if result.dim() != label.dim() {
+            return Err(Error::LabelShapeMismatch {
+                sample: i,
+                expected: result.dim(),
+                actual: label.dim(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// 按归约方式和权重合并每个样本的损失值和梯度
+fn reduce<T: Float>(
+    samples: Vec<(T, Mat<T>)>,
+    reduction: Reduction,
+    weights: Option<&[T]>,
+) -> LossOutput<T> {
+    let n = samples.len();
+    let mut losses = Vec::with_capacity(n);
+    let mut grads = Vec::with_capacity(n);
+    for (i, (l, g)) in samples.into_iter().enumerate() {
+        let w = weights.map_or(T::one(), |w| w[i]);
+        let scale = match reduction {
+            Reduction::Mean => w / T::of(n as f64),
+            Reduction::Sum | Reduction::None => w,
+        };
+        losses.push(w * l);
+        grads.push(g * scale);
+    }
+    let sum: T = losses.iter().copied().sum();
+    let loss = match reduction {
+        Reduction::Sum | Reduction::None => sum,
+        Reduction::Mean => sum / T::of(n as f64),
+    };
+    LossOutput {
+        loss,
+        losses,
+        grads,
+    }
 }
 
 /// sigmod(X) = 1/(1 + e^(-x))
-pub fn sigmod(x: f32) -> f32 {
-    1.0 / (1.0 + (-x).exp())
+pub fn sigmod<T: Float>(x: T) -> T {
+    T::one() / (T::one() + (-x).exp())
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{
+        layer_impls::{DenseLayerNoActive, SigmodLayer, SoftmaxLayer},
+        loss_impls::{CrossEntropy, MSE},
+        Error, Float, Layer, LayerCache, Loss, Mat, NeuralNetworkModel,
+    };
+
+    fn check_fit<T: Float>() {
+        let mut model = NeuralNetworkModel::<T>::new();
+        model.push_layer(DenseLayerNoActive::new(2, 2));
+        model.push_layer(SoftmaxLayer::new());
+        model.minimize(CrossEntropy::new());
+
+        let datas: Vec<Mat<T>> = vec![array![[1.], [0.]], array![[0.], [1.]]]
+            .into_iter()
+            .map(|m| m.mapv(T::of))
+            .collect();
+        let labels = datas.clone();
+        let before = model.evaluate(&datas, &labels).unwrap().loss;
+        assert!((before - T::of(2f64.ln())).abs() < T::of(1e-6));
+        for _ in 0..20 {
+            model.fit(&datas, &labels, T::of(0.5)).unwrap();
+        }
+        assert!(model.evaluate(&datas, &labels).unwrap().loss < before / T::of(2.));
+    }
+
+    #[test]
+    fn test_fit() {
+        check_fit::<f32>();
+        check_fit::<f64>();
+    }
+
+    // 用f64做梯度检查, 比较反向传播算出的w梯度和数值微分的结果
+    fn check_grads<L: Layer<f64>>(new_last: fn() -> L, loss: impl Loss<f64>) {
+        let mut dense = DenseLayerNoActive::<f64> {
+            w: array![[0.1, -0.2, 0.3], [0.4, 0.5, -0.6]],
+            b: array![[0.01], [-0.02]],
+        };
+        let input: Mat<f64> = array![[1.], [0.5], [-1.5]];
+        let label: Mat<f64> = array![[0.], [1.]];
+
+        let calc_loss = |dense: &mut DenseLayerNoActive<f64>| {
+            let mut last = new_last();
+            let (z, f1) = dense.forward(&input.view(), true);
+            let (a, f2) = last.forward(&z.view(), true);
+            let (l, g) = loss.calc(&a.view(), &label.view());
+            let (g, _) = last.backward(&g.view(), &f2);
+            let (_, grads): (Mat<f64>, LayerCache<f64>) = dense.backward(&g.view(), &f1);
+            (l, grads)
+        };
+
+        let (_, grads) = calc_loss(&mut dense);
+        let eps = 1e-6;
+        for i in 0..2 {
+            for k in 0..3 {
+                dense.w[(i, k)] += eps;
+                let (l1, _) = calc_loss(&mut dense);
+                dense.w[(i, k)] -= 2. * eps;
+                let (l2, _) = calc_loss(&mut dense);
+                dense.w[(i, k)] += eps;
+                let numeric = (l1 - l2) / (2. * eps);
+                assert!((numeric - grads[1][(i, k)]).abs() < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_grads_f64() {
+        check_grads(SoftmaxLayer::new, CrossEntropy::new());
+        check_grads(SigmodLayer::new, MSE::new());
+    }
+
+    // 错误类别的logit很大时正确类别的概率下溢为0, 梯度仍然是 p - y
+    #[test]
+    fn test_saturated_softmax() {
+        let mut model = NeuralNetworkModel::new();
+        model.push_layer(DenseLayerNoActive {
+            w: array![[200., 0.], [-200., 0.]],
+            b: array![[0.], [0.]],
+        });
+        model.push_layer(SoftmaxLayer::new());
+        model.minimize(CrossEntropy::new());
+
+        let datas = vec![array![[1.], [0.]]];
+        let labels = vec![array![[0.], [1.]]];
+        let loss = model.fit(&datas, &labels, 0.).unwrap();
+        assert!((loss + 1e-7f32.ln()).abs() < 1e-4);
+        let w_grads = &model.last_grads[0][1];
+        assert_eq!(w_grads.column(0).to_vec(), vec![1., -1.]);
+        assert!(model.layers.iter().all(|l| l
+            .params()
+            .iter()
+            .all(|(_, p)| p.iter().all(|v| v.is_finite()))));
+    }
+
+    #[test]
+    fn test_fit_errors() {
+        let mut model = NeuralNetworkModel::new();
+        model.push_layer(DenseLayerNoActive::new(2, 2));
+        model.push_layer(SoftmaxLayer::new());
+
+        let datas = vec![array![[1.], [0.]]];
+        let labels = vec![array![[1.], [0.]]];
+        assert_eq!(model.fit(&datas, &labels, 0.1), Err(Error::MissingLoss));
+
+        model.minimize(CrossEntropy::new());
+        assert_eq!(model.fit(&[], &[], 0.1), Err(Error::EmptyBatch));
+        assert_eq!(
+            model.fit(&[array![[1.], [0.], [0.]]], &labels, 0.1),
+            Err(Error::ShapeMismatch {
+                layer: 0,
+                expected: (2, 1),
+                actual: (3, 1)
+            })
+        );
+        assert_eq!(
+            model.fit(&datas, &[array![[1.], [0.], [0.]]], 0.1),
+            Err(Error::LabelShapeMismatch {
+                sample: 0,
+                expected: (2, 1),
+                actual: (3, 1)
+            })
+        );
+        assert_eq!(
+            model.fit_weighted(&datas, &labels, Some(&[1., 1.]), 0.1),
+            Err(Error::BatchSizeMismatch {
+                expected: 1,
+                actual: 2
+            })
+        );
+        assert_eq!(
+            model.predict(&array![[f32::NAN], [0.]].view()),
+            Err(Error::NaN { layer: Some(0) })
+        );
+    }
 }
@@ -0,0 +1,104 @@
+use crate::{sigmod, Float, Loss, Mat, MatView};
+
+// 概率取值限制在 [EPS, 1-EPS], 避免ln(0)和除0
+const EPS: f64 = 1e-7;
+
+/// 二分类交叉熵, 输出结果是每个神经元为正类的概率(sigmod的输出)
+/// L = -Σ y*ln(p) + (1-y)*ln(1-p)
+pub struct BinaryCrossEntropy {}
+
+impl BinaryCrossEntropy {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for BinaryCrossEntropy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Loss<T> for BinaryCrossEntropy {
+    // L对p的偏导 (p-y) / (p*(1-p))
+    fn calc(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>) {
+        let one = T::one();
+        let (lo, hi) = (T::of(EPS), T::of(1. - EPS));
+        let loss = result
+            .iter()
+            .zip(label.iter())
+            .fold(T::zero(), |acc, (p, y)| {
+                let p = p.max(lo).min(hi);
+                acc - (*y * p.ln() + (one - *y) * (one - p).ln())
+            });
+        let grads = Mat::from_shape_fn(result.raw_dim(), |(i, j)| {
+            let p = result[(i, j)].max(lo).min(hi);
+            (p - label[(i, j)]) / (p * (one - p))
+        });
+        (loss, grads.t().to_owned())
+    }
+}
+
+/// 输出结果是未经过sigmod的原始值(logits)的二分类交叉熵
+/// 把sigmod合并进损失函数计算, 数值上更稳定, 网络最后一层不需要sigmod
+/// L = Σ max(z,0) - z*y + ln(1 + e^(-|z|))
+pub struct BinaryCrossEntropyWithLogits {}
+
+impl BinaryCrossEntropyWithLogits {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for BinaryCrossEntropyWithLogits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Loss<T> for BinaryCrossEntropyWithLogits {
+    // L对z的偏导 sigmod(z) - y
+    fn calc(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>) {
+        let loss = result
+            .iter()
+            .zip(label.iter())
+            .fold(T::zero(), |acc, (z, y)| {
+                acc + z.max(T::zero()) - *z * *y + (-z.abs()).exp().ln_1p()
+            });
+        let grads = Mat::from_shape_fn(result.raw_dim(), |(i, j)| {
+            sigmod(result[(i, j)]) - label[(i, j)]
+        });
+        (loss, grads.t().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{sigmod, Loss, Mat};
+
+    use super::{BinaryCrossEntropy, BinaryCrossEntropyWithLogits};
+
+    #[test]
+    fn test() {
+        let z: Mat = array![[0.5], [-1.]];
+        let y = array![[1.], [0.]];
+        let p = z.map(|v| sigmod(*v));
+
+        let (loss, g) = BinaryCrossEntropy::new().calc(&p.view(), &y.view());
+        let (loss_logits, g_logits) =
+            BinaryCrossEntropyWithLogits::new().calc(&z.view(), &y.view());
+        let want = -(sigmod(0.5f32).ln() + (1. - sigmod(-1f32)).ln());
+        assert!((loss - want).abs() < 1e-5);
+        assert!((loss_logits - want).abs() < 1e-5);
+
+        // 经过sigmod的链式法则之后两者梯度一致
+        let g = &g * &p.map(|p| p * (1. - p)).t();
+        assert_eq!(g_logits.shape(), &[1, 2]);
+        assert!(g
+            .iter()
+            .zip(g_logits.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-5));
+    }
+}
@@ -1,44 +1,153 @@
-use crate::Loss;
+use crate::{Error, Float, Loss, Mat, MatView, Result};
 
+// 概率下限, 避免ln(0)
+const EPS: f64 = 1e-7;
+
+/// 交叉熵, 输出结果是每个类别的概率(softmax的输出)
+/// 期望结果可以是one-hot, 也可以是任意的概率分布(soft target)
+/// L = -Σ y*ln(p)
 pub struct CrossEntropy {
-    acc: usize,
-    total: usize,
+    label_smoothing: f64,
 }
 
 impl CrossEntropy {
     pub fn new() -> Self {
-        Self { acc: 0, total: 0 }
+        Self {
+            label_smoothing: 0.,
+        }
+    }
+    // 标签平滑, 期望结果变为 (1-ε)*y + ε/k, k是类别个数
+    // ε取值 [0, 1), 为0时不做平滑, 超出范围时返回Err
+    pub fn with_label_smoothing(epsilon: f64) -> Result<Self> {
+        if !(0. ..1.).contains(&epsilon) {
+            return Err(Error::InvalidParameter(format!(
+                "label smoothing must be in [0, 1), got {}",
+                epsilon
+            )));
+        }
+        Ok(Self {
+            label_smoothing: epsilon,
+        })
     }
 }
 
-impl Loss for CrossEntropy {
-    // 输出结果 和 期望结果 都是n行1列
-    fn sum_loss(&mut self, result: &crate::MatView, label: &crate::MatView) {
-        self.total += 1;
-        let mut max_idx = 0;
-        let mut max_v = f32::MIN;
-        for (i, v) in result.iter().enumerate() {
-            if *v > max_v {
-                max_idx = i;
-                max_v = *v;
-            }
-        }
-        if label[(max_idx, 0)] == 1. {
-            self.acc += 1;
-        }
+impl Default for CrossEntropy {
+    fn default() -> Self {
+        Self::new()
     }
-    // 1 - 正确率
-    fn loss(&self) -> f32 {
-        let r = 1. - self.acc as f32 / self.total as f32;
-        r
+}
+
+impl CrossEntropy {
+    // 平滑后的期望结果和损失值, p取下限EPS避免ln(0)
+    fn smooth_loss<T: Float>(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>) {
+        let eps = T::of(self.label_smoothing);
+        let k = T::of(label.len() as f64);
+        let label = label.map(|y| (T::one() - eps) * *y + eps / k);
+        let loss = result
+            .iter()
+            .zip(label.iter())
+            .fold(T::zero(), |acc, (p, y)| acc - *y * p.max(T::of(EPS)).ln());
+        (loss, label)
     }
+}
 
-    // 交叉熵梯度直接传label值, 网络最后一层必须是softmax
-    fn grads(&mut self, _result: &crate::MatView, label: &crate::MatView) -> crate::Mat {
-        label.to_owned()
+impl<T: Float> Loss<T> for CrossEntropy {
+    // L对p的偏导是 -y/p, 和softmax层的反向传播组合后即为 p - y
+    // p取和损失值一样的下限EPS, 避免除0
+    fn calc(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>) {
+        let (loss, label) = self.smooth_loss(result, label);
+        let grads = Mat::from_shape_fn(result.raw_dim(), |(i, j)| {
+            -label[(i, j)] / result[(i, j)].max(T::of(EPS))
+        });
+        (loss, grads.t().to_owned())
     }
 
-    fn reset(&mut self) {
-        *self = Self::new();
+    // 对softmax输入z的偏导是 p*Σy - y, y是概率分布时即为 p - y
+    fn calc_with_softmax(&self, result: &MatView<T>, label: &MatView<T>) -> Option<(T, Mat<T>)> {
+        let (loss, label) = self.smooth_loss(result, label);
+        let sum = label.sum();
+        let grads = Mat::from_shape_fn(result.raw_dim(), |(i, j)| {
+            result[(i, j)] * sum - label[(i, j)]
+        });
+        Some((loss, grads.t().to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{Error, Loss, Mat, Reduction};
+
+    use super::CrossEntropy;
+
+    #[test]
+    fn test() {
+        let l = CrossEntropy::new();
+        let results: Vec<Mat> = vec![array![[0.5], [0.5]], array![[0.25], [0.75]]];
+        let labels = vec![array![[1.], [0.]], array![[0.], [1.]]];
+
+        let out = l.compute(&results, &labels, Reduction::Mean, None).unwrap();
+        let want = -(0.5f32.ln() + 0.75f32.ln()) / 2.;
+        assert!((out.loss - want).abs() < 1e-6);
+        assert_eq!(out.grads[0], array![[-1., 0.]]);
+
+        let out = l
+            .compute(&results, &labels, Reduction::Sum, Some(&[2., 0.]))
+            .unwrap();
+        assert!((out.loss + 2. * 0.5f32.ln()).abs() < 1e-6);
+        assert_eq!(out.grads[0], array![[-4., 0.]]);
+        assert_eq!(out.grads[1], array![[0., 0.]]);
+
+        // 不归约时报告的损失和梯度一致, 都是每个样本的和
+        let out = l.compute(&results, &labels, Reduction::None, None).unwrap();
+        assert_eq!(out.losses.len(), 2);
+        assert!((out.losses[1] + 0.75f32.ln()).abs() < 1e-6);
+        assert!((out.loss + 0.5f32.ln() + 0.75f32.ln()).abs() < 1e-6);
+        assert_eq!(out.grads[0], array![[-2., 0.]]);
+
+        // 直接调用compute时也检查批量
+        assert_eq!(
+            l.compute(&results, &labels, Reduction::Mean, Some(&[1.]))
+                .unwrap_err(),
+            Error::BatchSizeMismatch {
+                expected: 2,
+                actual: 1
+            }
+        );
+        assert!(l
+            .compute(&results, &labels[..1], Reduction::Mean, None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_label_smoothing() {
+        let p: Mat = array![[0.8], [0.1], [0.1]];
+        let y = array![[1.], [0.], [0.]];
+        let smooth = array![[0.8], [0.1], [0.1]];
+
+        // 平滑后的期望结果和soft target结果一致
+        let (l1, g1) = CrossEntropy::with_label_smoothing(0.3)
+            .unwrap()
+            .calc(&p.view(), &y.view());
+        let (l2, g2) = CrossEntropy::new().calc(&p.view(), &smooth.view());
+        assert!((l1 - l2).abs() < 1e-6);
+        assert!(g1.iter().zip(g2.iter()).all(|(a, b)| (a - b).abs() < 1e-5));
+        // 输出等于期望分布时梯度经过softmax后为0, 即 -y/p 全为-1
+        assert!(g2.iter().all(|g| (g + 1.).abs() < 1e-5));
+        // 合并softmax后梯度为 p - y
+        let (l3, g3) = CrossEntropy::new()
+            .calc_with_softmax(&p.view(), &y.view())
+            .unwrap();
+        assert!((l3 + 0.8f32.ln()).abs() < 1e-6);
+        assert_eq!(g3, array![[-0.19999999, 0.1, 0.1]]);
+
+        // 超出范围时返回Err
+        for eps in [-0.1, 1., f64::NAN] {
+            assert!(matches!(
+                CrossEntropy::with_label_smoothing(eps),
+                Err(Error::InvalidParameter(_))
+            ));
+        }
     }
 }
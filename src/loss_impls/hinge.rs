@@ -0,0 +1,60 @@
+use crate::{metrics::argmax, Float, Loss, Mat, MatView};
+
+/// 多分类hinge损失(Weston-Watkins), 输出结果是每个类别的得分, 期望结果是one-hot
+/// L = Σ(j != c) max(0, margin + s[j] - s[c]), c是正确的类别
+pub struct Hinge {
+    margin: f64,
+}
+
+impl Hinge {
+    pub fn new() -> Self {
+        Self::with_margin(1.)
+    }
+    pub fn with_margin(margin: f64) -> Self {
+        Hinge { margin }
+    }
+}
+
+impl Default for Hinge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Loss<T> for Hinge {
+    // 违反间隔的类别j偏导为1, 正确类别c的偏导为 -违反间隔的类别个数
+    fn calc(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>) {
+        // 期望结果中值最大的下标就是正确的类别
+        let c = argmax(label);
+        let sc = result[(c, 0)];
+        let mut loss = T::zero();
+        let mut grads = Mat::zeros((1, result.len()));
+        for (j, s) in result.iter().enumerate() {
+            let m = T::of(self.margin) + *s - sc;
+            if j != c && m > T::zero() {
+                loss += m;
+                grads[(0, j)] = T::one();
+                grads[(0, c)] -= T::one();
+            }
+        }
+        (loss, grads)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{Loss, Mat};
+
+    use super::Hinge;
+
+    #[test]
+    fn test() {
+        let s: Mat = array![[3.], [1.], [2.5]];
+        let y = array![[1.], [0.], [0.]];
+        let (loss, grads) = Hinge::new().calc(&s.view(), &y.view());
+        assert_eq!(loss, 0.5);
+        assert_eq!(grads, array![[-1., 0., 1.]]);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{Float, Loss, Mat, MatView};
+
+/// Huber损失, 误差较小时是平方误差, 较大时是线性误差, 对离群值不敏感
+/// delta为1时即smooth L1
+/// |r| <= delta: 0.5*r^2, 否则 delta*(|r| - 0.5*delta), r = p - y
+pub struct Huber {
+    delta: f64,
+}
+
+impl Huber {
+    pub fn new(delta: f64) -> Self {
+        Huber { delta }
+    }
+    // delta为1的Huber损失
+    pub fn smooth_l1() -> Self {
+        Self::new(1.)
+    }
+}
+
+impl<T: Float> Loss<T> for Huber {
+    // |r| <= delta 时偏导是r, 否则是 delta*sign(r)
+    fn calc(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>) {
+        let delta = T::of(self.delta);
+        let half = T::of(0.5);
+        let diff = result - label;
+        let loss = diff.fold(T::zero(), |acc, r| {
+            let r = r.abs();
+            if r <= delta {
+                acc + half * r * r
+            } else {
+                acc + delta * (r - half * delta)
+            }
+        });
+        let grads = diff.map(|r| r.max(-delta).min(delta));
+        (loss, grads.t().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{Loss, Mat};
+
+    use super::Huber;
+
+    #[test]
+    fn test() {
+        let l = Huber::smooth_l1();
+        let p: Mat = array![[0.5], [3.], [-2.]];
+        let y = array![[0.], [0.], [0.]];
+        let (loss, grads) = l.calc(&p.view(), &y.view());
+        assert_eq!(loss, 0.125 + 2.5 + 1.5);
+        assert_eq!(grads, array![[0.5, 1., -1.]]);
+        assert_eq!(l.calc(&y.view(), &y.view()).0, 0.);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{Float, Loss, Mat, MatView};
+
+// 概率下限, 避免ln(0)和除0
+const EPS: f64 = 1e-7;
+
+/// KL散度, 衡量输出的概率分布p和期望的概率分布y的差异
+/// L = Σ y*(ln(y) - ln(p)), y为0的项不参与计算
+pub struct KLDivergence {}
+
+impl KLDivergence {
+    pub fn new() -> Self {
+        KLDivergence {}
+    }
+}
+
+impl Default for KLDivergence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Loss<T> for KLDivergence {
+    // L对p的偏导 -y/p, 和交叉熵一样, 配合softmax使用
+    fn calc(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>) {
+        let eps = T::of(EPS);
+        let loss = result
+            .iter()
+            .zip(label.iter())
+            .fold(T::zero(), |acc, (p, y)| {
+                if *y > T::zero() {
+                    acc + *y * (y.ln() - p.max(eps).ln())
+                } else {
+                    acc
+                }
+            });
+        let grads = Mat::from_shape_fn(result.raw_dim(), |(i, j)| {
+            -label[(i, j)] / result[(i, j)].max(eps)
+        });
+        (loss, grads.t().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{Loss, Mat};
+
+    use super::KLDivergence;
+
+    #[test]
+    fn test() {
+        let l = KLDivergence::new();
+        let y: Mat = array![[0.25], [0.75], [0.]];
+        assert_eq!(l.calc(&y.view(), &y.view()).0, 0.);
+
+        let p = array![[0.5], [0.5], [0.]];
+        let (loss, grads) = l.calc(&p.view(), &y.view());
+        let want = 0.25 * (0.25f32 / 0.5).ln() + 0.75 * (0.75f32 / 0.5).ln();
+        assert!((loss - want).abs() < 1e-6);
+        assert_eq!(grads, array![[-0.5, -1.5, 0.]]);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{Float, Loss, Mat, MatView};
+
+/// 平均绝对误差 L = Σ|p - y|
+pub struct MAE {}
+
+impl MAE {
+    pub fn new() -> Self {
+        MAE {}
+    }
+}
+
+impl Default for MAE {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Loss<T> for MAE {
+    // 偏导是sign(p - y), 在0处不可导, 取0
+    fn calc(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>) {
+        let diff = result - label;
+        let loss = diff.map(|v| v.abs()).sum();
+        let grads = diff.map(|r| {
+            if *r > T::zero() {
+                T::one()
+            } else if *r < T::zero() {
+                -T::one()
+            } else {
+                T::zero()
+            }
+        });
+        (loss, grads.t().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{Loss, Mat};
+
+    use super::MAE;
+
+    #[test]
+    fn test() {
+        let p: Mat = array![[0.5], [-2.], [1.]];
+        let y = array![[0.], [1.], [1.]];
+        let (loss, grads) = MAE::new().calc(&p.view(), &y.view());
+        assert_eq!(loss, 3.5);
+        // 误差为0处取次梯度0
+        assert_eq!(grads, array![[1., -1., 0.]]);
+        let (loss, grads) = MAE::new().calc(&y.view(), &y.view());
+        assert_eq!(loss, 0.);
+        assert_eq!(grads, array![[0., 0., 0.]]);
+    }
+}
@@ -2,3 +2,15 @@ mod cross_entropy;
 pub use cross_entropy::CrossEntropy;
 mod mse;
 pub use mse::MSE;
+mod binary_cross_entropy;
+pub use binary_cross_entropy::{BinaryCrossEntropy, BinaryCrossEntropyWithLogits};
+mod nll;
+pub use nll::NLL;
+mod huber;
+pub use huber::Huber;
+mod mae;
+pub use mae::MAE;
+mod hinge;
+pub use hinge::Hinge;
+mod kl_divergence;
+pub use kl_divergence::KLDivergence;
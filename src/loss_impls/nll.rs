@@ -0,0 +1,43 @@
+use crate::{Float, Loss, Mat, MatView};
+
+/// 负对数似然, 输出结果是每个类别概率的对数 ln(p)
+/// L = -Σ y*ln(p)
+pub struct NLL {}
+
+impl NLL {
+    pub fn new() -> Self {
+        NLL {}
+    }
+}
+
+impl Default for NLL {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Loss<T> for NLL {
+    // L对ln(p)的偏导就是 -y
+    fn calc(&self, result: &MatView<T>, label: &MatView<T>) -> (T, Mat<T>) {
+        let loss = -(result * label).sum();
+        (loss, label.map(|y| -*y).t().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{Loss, Mat};
+
+    use super::NLL;
+
+    #[test]
+    fn test() {
+        let p: Mat = array![[0.2], [0.5], [0.3]];
+        let y = array![[0.], [1.], [0.]];
+        let (loss, grads) = NLL::new().calc(&p.mapv(f32::ln).view(), &y.view());
+        assert!((loss - 2f32.ln()).abs() < 1e-6);
+        assert_eq!(grads, array![[0., -1., 0.]]);
+    }
+}
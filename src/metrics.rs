@@ -0,0 +1,123 @@
+use crate::{Float, Mat, MatView};
+
+// 最大值所在的下标, 即分类结果
+pub fn argmax<T: Float>(result: &MatView<T>) -> usize {
+    let mut max_idx = 0;
+    let mut max_v = T::min_value();
+    for (i, v) in result.iter().enumerate() {
+        if *v > max_v {
+            max_idx = i;
+            max_v = *v;
+        }
+    }
+    max_idx
+}
+
+// 正确率, 输出结果 和 期望结果 都是n行1列
+// 期望结果可以是one-hot也可以是soft target, 取概率最大的类别作为正确答案
+pub fn accuracy<T: Float>(results: &[Mat<T>], labels: &[Mat<T>]) -> f32 {
+    let mut acc = 0;
+    for (result, label) in results.iter().zip(labels) {
+        if argmax(&result.view()) == argmax(&label.view()) {
+            acc += 1;
+        }
+    }
+    acc as f32 / results.len() as f32
+}
+
+/// 混淆矩阵, counts[真实类别][预测类别]是样本个数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfusionMatrix {
+    pub counts: Vec<Vec<usize>>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(classes: usize) -> Self {
+        ConfusionMatrix {
+            counts: vec![vec![0; classes]; classes],
+        }
+    }
+
+    // 真实类别和预测类别都是类别编号
+    pub fn from_labels(labels: &[usize], predicted: &[usize], classes: usize) -> Self {
+        let mut m = Self::new(classes);
+        for (l, p) in labels.iter().zip(predicted) {
+            m.add(*l, *p);
+        }
+        m
+    }
+
+    // 和accuracy一样, 输出结果和期望结果都取概率最大的类别
+    pub fn from_results<T: Float>(results: &[Mat<T>], labels: &[Mat<T>]) -> Self {
+        let classes = labels.first().map_or(0, |l| l.len());
+        let mut m = Self::new(classes);
+        for (result, label) in results.iter().zip(labels) {
+            m.add(argmax(&label.view()), argmax(&result.view()));
+        }
+        m
+    }
+
+    pub fn add(&mut self, label: usize, predicted: usize) {
+        self.counts[label][predicted] += 1;
+    }
+
+    pub fn classes(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().flatten().sum()
+    }
+
+    // 对角线上的样本占比
+    pub fn accuracy(&self) -> f64 {
+        let correct: usize = (0..self.classes()).map(|i| self.counts[i][i]).sum();
+        correct as f64 / self.total().max(1) as f64
+    }
+
+    // 每行除以该行的总数, 即每个真实类别被预测成各个类别的比例
+    pub fn row_fractions(&self) -> Vec<Vec<f64>> {
+        self.counts
+            .iter()
+            .map(|row| {
+                let sum = row.iter().sum::<usize>().max(1) as f64;
+                row.iter().map(|c| *c as f64 / sum).collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::Mat;
+
+    use super::{accuracy, argmax, ConfusionMatrix};
+
+    #[test]
+    fn test() {
+        assert_eq!(argmax(&array![[0.1], [0.7], [0.2]].view()), 1);
+        assert_eq!(argmax(&array![[0.1f64], [0.7], [0.9]].view()), 2);
+        let results: Vec<Mat> = vec![array![[0.1], [0.9]], array![[0.6], [0.4]]];
+        let labels = vec![array![[0.], [1.]], array![[0.], [1.]]];
+        assert_eq!(accuracy(&results, &labels), 0.5);
+        let labels = vec![array![[0.05], [0.95]], array![[0.7], [0.3]]];
+        assert_eq!(accuracy(&results, &labels), 1.);
+    }
+
+    #[test]
+    fn test_confusion() {
+        let m = ConfusionMatrix::from_labels(&[0, 0, 1, 2, 2, 2], &[0, 1, 1, 2, 2, 0], 3);
+        assert_eq!(m.counts, vec![vec![1, 1, 0], vec![0, 1, 0], vec![1, 0, 2]]);
+        assert_eq!(m.total(), 6);
+        assert_eq!(m.accuracy(), 4. / 6.);
+        assert_eq!(m.row_fractions()[0], vec![0.5, 0.5, 0.]);
+
+        let results: Vec<Mat> = vec![array![[0.1], [0.9]], array![[0.6], [0.4]]];
+        let labels = vec![array![[0.], [1.]], array![[0.], [1.]]];
+        let m = ConfusionMatrix::from_results(&results, &labels);
+        assert_eq!(m.counts, vec![vec![0, 0], vec![1, 1]]);
+        assert_eq!(m.accuracy() as f32, accuracy(&results, &labels));
+    }
+}
@@ -0,0 +1,595 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::util::write_atomic;
+use crate::{Error, Result};
+
+/// 一个超参数的取值范围
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Param {
+    // 离散取值
+    Choice(Vec<f64>),
+    // [low, high] 中的整数, 比如批量大小, 隐藏层大小
+    IntRange { low: i64, high: i64 },
+    // [low, high] 中均匀分布
+    Uniform { low: f64, high: f64 },
+    // 对数均匀分布, 适合学习率这类跨几个数量级的参数
+    LogUniform { low: f64, high: f64 },
+}
+
+impl Param {
+    // 范围的下限不能大于上限, 对数均匀分布的下限必须大于0
+    fn validate(&self, name: &str) -> Result<()> {
+        let ok = match self {
+            Param::Choice(values) => !values.is_empty() && values.iter().all(|v| v.is_finite()),
+            Param::IntRange { low, high } => low <= high,
+            Param::Uniform { low, high } => low.is_finite() && high.is_finite() && low <= high,
+            Param::LogUniform { low, high } => high.is_finite() && *low > 0. && low <= high,
+        };
+        if !ok {
+            return Err(Error::InvalidParameter(format!(
+                "hyperparameter {:?} has an invalid range {:?}",
+                name, self
+            )));
+        }
+        Ok(())
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        match self {
+            Param::Choice(values) => values[rng.gen_range(0..values.len())],
+            Param::IntRange { low, high } => rng.gen_range(*low..=*high) as f64,
+            Param::Uniform { low, high } => rng.gen_range(*low..=*high),
+            Param::LogUniform { low, high } => rng.gen_range(low.ln()..=high.ln()).exp(),
+        }
+    }
+
+    // 网格搜索的取值, 连续分布没有网格
+    fn grid(&self) -> Option<Vec<f64>> {
+        match self {
+            Param::Choice(values) => Some(values.clone()),
+            Param::IntRange { low, high } => Some((*low..=*high).map(|v| v as f64).collect()),
+            _ => None,
+        }
+    }
+}
+
+/// 一组超参数的取值, 整数参数也保存为f64
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Params(pub BTreeMap<String, f64>);
+
+impl Params {
+    // 参数不存在时返回Err, 在objective中用?传出, 这个试验记为出错
+    pub fn get(&self, name: &str) -> Result<f64> {
+        self.0
+            .get(name)
+            .copied()
+            .ok_or_else(|| Error::InvalidParameter(format!("unknown hyperparameter {:?}", name)))
+    }
+
+    pub fn get_usize(&self, name: &str) -> Result<usize> {
+        Ok(self.get(name)?.round() as usize)
+    }
+}
+
+/// 搜索空间, 参数按名字排序
+/// SearchSpace::new().log_uniform("learning_rate", 1e-3, 1.).choice("batch_size", &[16., 32.])
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchSpace {
+    pub params: BTreeMap<String, Param>,
+}
+
+impl SearchSpace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn param(mut self, name: &str, param: Param) -> Self {
+        self.params.insert(name.to_string(), param);
+        self
+    }
+
+    pub fn choice(self, name: &str, values: &[f64]) -> Self {
+        self.param(name, Param::Choice(values.to_vec()))
+    }
+
+    pub fn int_range(self, name: &str, low: i64, high: i64) -> Self {
+        self.param(name, Param::IntRange { low, high })
+    }
+
+    pub fn uniform(self, name: &str, low: f64, high: f64) -> Self {
+        self.param(name, Param::Uniform { low, high })
+    }
+
+    pub fn log_uniform(self, name: &str, low: f64, high: f64) -> Self {
+        self.param(name, Param::LogUniform { low, high })
+    }
+
+    // 检查每个参数的取值范围, Search::run开始前会调用
+    pub fn validate(&self) -> Result<()> {
+        for (name, p) in &self.params {
+            p.validate(name)?;
+        }
+        Ok(())
+    }
+
+    pub fn sample(&self, rng: &mut StdRng) -> Params {
+        Params(
+            self.params
+                .iter()
+                .map(|(name, p)| (name.clone(), p.sample(rng)))
+                .collect(),
+        )
+    }
+
+    // 所有离散取值的组合, 有连续分布的参数时返回Err
+    pub fn grid(&self) -> Result<Vec<Params>> {
+        self.validate()?;
+        let mut all = vec![Params::default()];
+        for (name, p) in &self.params {
+            let values = p.grid().ok_or_else(|| {
+                Error::InvalidParameter(format!("grid search needs discrete values for {:?}", name))
+            })?;
+            all = all
+                .iter()
+                .flat_map(|params| {
+                    values.iter().map(move |v| {
+                        let mut params = params.clone();
+                        params.0.insert(name.clone(), *v);
+                        params
+                    })
+                })
+                .collect();
+        }
+        Ok(all)
+    }
+}
+
+/// 搜索策略, 预算是训练轮数
+#[derive(Debug, Clone, PartialEq)]
+pub enum Strategy {
+    // 所有组合, 每个用完整预算
+    Grid,
+    // trials个随机配置, 每个用完整预算
+    Random {
+        trials: usize,
+    },
+    // 连续减半: trials个随机配置先各训练min_budget轮
+    // 每一级只保留最好的 1/eta, 预算乘以eta, 直到完整预算
+    SuccessiveHalving {
+        trials: usize,
+        min_budget: usize,
+        eta: usize,
+    },
+    // 用不同的起始预算运行多组连续减半, 不需要事先选定trials和min_budget
+    Hyperband {
+        eta: usize,
+    },
+}
+
+/// 一次试验的记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trial {
+    pub id: usize,
+    pub params: Params,
+    // 训练轮数
+    pub budget: usize,
+    // 出错时为None, 出错的试验重新运行搜索时会再试一次
+    pub score: Option<f64>,
+    pub error: Option<String>,
+    // 花费的秒数
+    pub duration: f64,
+}
+
+/// 所有试验的记录, 保存为json, 重新运行同样的搜索时跳过已成功的试验
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchLog {
+    pub trials: Vec<Trial>,
+}
+
+impl SearchLog {
+    // 文件不存在时返回空记录
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(s) => Ok(serde_json::from_str(&s)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // 原子地写入, 中断时不会留下写了一半的文件
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        write_atomic(
+            path.as_ref(),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )
+    }
+
+    // 只返回成功的试验, 出错的要重新运行
+    fn find(&self, params: &Params, budget: usize) -> Option<&Trial> {
+        self.trials
+            .iter()
+            .find(|t| t.budget == budget && t.params == *params && t.score.is_some())
+    }
+}
+
+/// 搜索结果
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    // 完整预算的试验中分数最好的
+    pub best: Option<Trial>,
+    // 本次搜索用到的所有试验, 包括从记录中恢复的
+    pub trials: Vec<Trial>,
+}
+
+/// 超参数搜索
+pub struct Search {
+    pub space: SearchSpace,
+    pub strategy: Strategy,
+    // 完整预算, 即最多训练的轮数
+    pub budget: usize,
+    // 同时运行的试验个数
+    pub threads: usize,
+    pub seed: u64,
+    // 分数越大越好, 比如 val_accuracy; 为false时越小越好, 比如 val_loss
+    pub maximize: bool,
+    // 试验记录文件, 每完成一个试验保存一次
+    pub log_path: Option<PathBuf>,
+    // 每完成一个试验打印一行, 默认不打印
+    pub verbose: bool,
+}
+
+impl Search {
+    pub fn new(space: SearchSpace, strategy: Strategy, budget: usize) -> Self {
+        Search {
+            space,
+            strategy,
+            budget,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: 0,
+            maximize: true,
+            log_path: None,
+            verbose: false,
+        }
+    }
+
+    // objective用给定的参数构建并训练模型, 训练budget轮, 返回验证集上的分数
+    // 出错的试验记录为失败, 不会中断搜索, 下次运行时重试
+    // 搜索空间不合法时返回InvalidInput, 不运行任何试验
+    pub fn run<F>(&self, objective: F) -> io::Result<SearchResult>
+    where
+        F: Fn(&Params, usize) -> Result<f64> + Sync,
+    {
+        let invalid = |e: Error| io::Error::new(io::ErrorKind::InvalidInput, e);
+        self.space.validate().map_err(invalid)?;
+        if self.budget == 0 {
+            return Err(invalid(Error::InvalidParameter(
+                "search budget must be at least 1".into(),
+            )));
+        }
+        let grid = match self.strategy {
+            Strategy::Grid => self.space.grid().map_err(invalid)?,
+            _ => vec![],
+        };
+        let log = match &self.log_path {
+            Some(path) => SearchLog::load(path)?,
+            None => SearchLog::default(),
+        };
+        let state = RunState {
+            search: self,
+            objective: &objective,
+            log: Mutex::new(log),
+        };
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut trials = vec![];
+        match &self.strategy {
+            Strategy::Grid => {
+                trials = state.run_batch(grid, self.budget)?;
+            }
+            Strategy::Random { trials: n } => {
+                let configs = (0..*n).map(|_| self.space.sample(&mut rng)).collect();
+                trials = state.run_batch(configs, self.budget)?;
+            }
+            Strategy::SuccessiveHalving {
+                trials: n,
+                min_budget,
+                eta,
+            } => {
+                let configs = (0..*n).map(|_| self.space.sample(&mut rng)).collect();
+                state.successive_halving(configs, *min_budget, *eta, &mut trials)?;
+            }
+            Strategy::Hyperband { eta } => {
+                let eta = (*eta).max(2);
+                // s_max = floor(log_eta(budget))
+                let mut s_max = 0;
+                while eta.pow(s_max + 1) <= self.budget {
+                    s_max += 1;
+                }
+                for s in (0..=s_max).rev() {
+                    let n = ((s_max + 1) as f64 / (s + 1) as f64 * eta.pow(s) as f64).ceil();
+                    let min_budget = (self.budget / eta.pow(s)).max(1);
+                    let configs = (0..n as usize)
+                        .map(|_| self.space.sample(&mut rng))
+                        .collect();
+                    state.successive_halving(configs, min_budget, eta, &mut trials)?;
+                }
+            }
+        }
+
+        let best = trials
+            .iter()
+            .filter(|t| t.budget == self.budget && t.score.is_some())
+            .max_by(|a, b| {
+                let (a, b) = (a.score.unwrap(), b.score.unwrap());
+                if self.maximize {
+                    a.total_cmp(&b)
+                } else {
+                    b.total_cmp(&a)
+                }
+            })
+            .cloned();
+        Ok(SearchResult { best, trials })
+    }
+}
+
+// 一次搜索运行时共享的状态
+struct RunState<'a, F> {
+    search: &'a Search,
+    objective: &'a F,
+    log: Mutex<SearchLog>,
+}
+
+impl<F: Fn(&Params, usize) -> Result<f64> + Sync> RunState<'_, F> {
+    // 在多个线程上运行一批试验, 已经有记录的直接使用记录, 结果和configs顺序一致
+    fn run_batch(&self, configs: Vec<Params>, budget: usize) -> io::Result<Vec<Trial>> {
+        let results: Vec<Mutex<Option<Trial>>> = configs.iter().map(|_| Mutex::new(None)).collect();
+        let next = AtomicUsize::new(0);
+        let error: Mutex<Option<io::Error>> = Mutex::new(None);
+        std::thread::scope(|scope| {
+            for _ in 0..self.search.threads.max(1).min(configs.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= configs.len() || error.lock().unwrap().is_some() {
+                        break;
+                    }
+                    match self.run_trial(&configs[i], budget) {
+                        Ok(trial) => *results[i].lock().unwrap() = Some(trial),
+                        Err(e) => *error.lock().unwrap() = Some(e),
+                    }
+                });
+            }
+        });
+        if let Some(e) = error.into_inner().unwrap() {
+            return Err(e);
+        }
+        Ok(results
+            .into_iter()
+            .map(|r| r.into_inner().unwrap().unwrap())
+            .collect())
+    }
+
+    fn run_trial(&self, params: &Params, budget: usize) -> io::Result<Trial> {
+        if let Some(trial) = self.log.lock().unwrap().find(params, budget) {
+            return Ok(trial.clone());
+        }
+        let start = Instant::now();
+        let (score, error) = match (self.objective)(params, budget) {
+            Ok(score) => (Some(score), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let mut log = self.log.lock().unwrap();
+        let trial = Trial {
+            id: log.trials.len(),
+            params: params.clone(),
+            budget,
+            score,
+            error,
+            duration: start.elapsed().as_secs_f64(),
+        };
+        if self.search.verbose {
+            match (&trial.score, &trial.error) {
+                (Some(score), _) => println!(
+                    "trial {}: {:?}, budget: {}, score: {}",
+                    trial.id, trial.params.0, budget, score
+                ),
+                (None, error) => println!(
+                    "trial {}: {:?}, budget: {}, error: {}",
+                    trial.id,
+                    trial.params.0,
+                    budget,
+                    error.as_deref().unwrap_or("")
+                ),
+            }
+        }
+        log.trials.push(trial.clone());
+        if let Some(path) = &self.search.log_path {
+            log.save(path)?;
+        }
+        Ok(trial)
+    }
+
+    // 每一级保留最好的 1/eta 个配置, 预算乘以eta, 最后一级用完整预算
+    fn successive_halving(
+        &self,
+        mut configs: Vec<Params>,
+        min_budget: usize,
+        eta: usize,
+        trials: &mut Vec<Trial>,
+    ) -> io::Result<()> {
+        let eta = eta.max(2);
+        let mut budget = min_budget.clamp(1, self.search.budget);
+        loop {
+            let mut rung = self.run_batch(configs, budget)?;
+            trials.extend(rung.iter().cloned());
+            if budget >= self.search.budget || rung.len() <= 1 {
+                return Ok(());
+            }
+            // 出错的试验排在最后
+            rung.sort_by(|a, b| match (a.score, b.score) {
+                (Some(a), Some(b)) if self.search.maximize => b.total_cmp(&a),
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            });
+            let keep = (rung.len() / eta).max(1);
+            configs = rung.into_iter().take(keep).map(|t| t.params).collect();
+            budget = (budget * eta).min(self.search.budget);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::Error;
+
+    use super::{Params, Search, SearchLog, SearchSpace, Strategy};
+
+    // 分数在 lr = 0.1 时最大, 预算越多越接近真实值
+    fn objective(p: &Params, budget: usize) -> crate::Result<f64> {
+        let lr = p.get("lr")?;
+        if lr > 0.9 {
+            return Err(Error::NaN { layer: None });
+        }
+        Ok(-(lr.log10() + 1.).abs() - 1. / budget as f64)
+    }
+
+    #[test]
+    fn test() {
+        let space = SearchSpace::new()
+            .choice("lr", &[0.01, 0.1, 1.])
+            .int_range("hidden", 1, 2);
+        assert_eq!(space.grid().unwrap().len(), 6);
+
+        let search = Search::new(space, Strategy::Grid, 4);
+        let result = search.run(objective).unwrap();
+        assert_eq!(result.trials.len(), 6);
+        let best = result.best.unwrap();
+        assert_eq!(best.params.get("lr"), Ok(0.1));
+        assert!(best.params.get("missing").is_err());
+        assert!(result.trials.iter().any(|t| t.error.is_some()));
+
+        let space = SearchSpace::new()
+            .log_uniform("lr", 1e-3, 0.5)
+            .uniform("momentum", 0., 1.);
+        let mut search = Search::new(space, Strategy::Random { trials: 8 }, 4);
+        search.threads = 3;
+        let a = search.run(objective).unwrap();
+        let b = search.run(objective).unwrap();
+        assert_eq!(a.trials.len(), 8);
+        // 同一个种子得到同样的配置, id和耗时和线程的执行顺序有关
+        let (a, b) = (a.best.unwrap(), b.best.unwrap());
+        assert_eq!((&a.params, a.score), (&b.params, b.score));
+        let lr = a.params.get("lr").unwrap();
+        assert!((1e-3..=0.5).contains(&lr));
+    }
+
+    #[test]
+    fn test_invalid() {
+        // 搜索空间不合法时不运行任何试验
+        let calls = AtomicUsize::new(0);
+        let run = |space: SearchSpace, strategy: Strategy| {
+            Search::new(space, strategy, 4).run(|p: &Params, budget| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                objective(p, budget)
+            })
+        };
+        let random = Strategy::Random { trials: 2 };
+        for space in [
+            SearchSpace::new().uniform("lr", 1., 0.),
+            SearchSpace::new().log_uniform("lr", 0., 1.),
+            SearchSpace::new().int_range("hidden", 3, 1),
+            SearchSpace::new().choice("lr", &[]),
+        ] {
+            assert!(space.validate().is_err());
+            let err = run(space, random.clone()).err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+        let space = SearchSpace::new().uniform("lr", 0., 1.);
+        assert!(space.grid().is_err());
+        assert!(run(space, Strategy::Grid).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_halving() {
+        let path =
+            std::env::temp_dir().join(format!("hello-nn-search-{}.json", std::process::id()));
+        let space = SearchSpace::new().log_uniform("lr", 1e-3, 0.5);
+        let mut search = Search::new(
+            space,
+            Strategy::SuccessiveHalving {
+                trials: 9,
+                min_budget: 1,
+                eta: 3,
+            },
+            9,
+        );
+        search.log_path = Some(path.clone());
+        let calls = AtomicUsize::new(0);
+        let run = |search: &Search| {
+            search
+                .run(|p: &Params, budget| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    objective(p, budget)
+                })
+                .unwrap()
+        };
+        let result = run(&search);
+        // 9个配置训练1轮, 3个训练3轮, 1个训练9轮
+        let budgets: Vec<usize> = result.trials.iter().map(|t| t.budget).collect();
+        assert_eq!(budgets.iter().filter(|b| **b == 1).count(), 9);
+        assert_eq!(budgets.iter().filter(|b| **b == 3).count(), 3);
+        assert_eq!(budgets.iter().filter(|b| **b == 9).count(), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 13);
+        assert_eq!(result.best.as_ref().unwrap().budget, 9);
+        assert_eq!(SearchLog::load(&path).unwrap().trials.len(), 13);
+
+        // 从记录恢复, 不再重新训练
+        let again = run(&search);
+        assert_eq!(calls.load(Ordering::SeqCst), 13);
+        assert_eq!(again.best, result.best);
+
+        search.strategy = Strategy::Hyperband { eta: 3 };
+        let result = run(&search);
+        assert_eq!(result.best.unwrap().budget, 9);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_retry() {
+        let path =
+            std::env::temp_dir().join(format!("hello-nn-search-retry-{}.json", std::process::id()));
+        let space = SearchSpace::new().choice("lr", &[0.1, 1.]);
+        let mut search = Search::new(space, Strategy::Grid, 2);
+        search.log_path = Some(path.clone());
+        let calls = AtomicUsize::new(0);
+        let run = |search: &Search| {
+            search
+                .run(|p: &Params, budget| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    objective(p, budget)
+                })
+                .unwrap()
+        };
+        let first = run(&search);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(first.trials.iter().filter(|t| t.error.is_some()).count(), 1);
+
+        // 成功的试验跳过, 出错的试验重新运行, 两次都留在记录里
+        run(&search);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        let log = SearchLog::load(&path).unwrap();
+        assert_eq!(log.trials.len(), 3);
+        assert_eq!(log.trials.iter().filter(|t| t.error.is_some()).count(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+}
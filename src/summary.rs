@@ -0,0 +1,150 @@
+use std::fmt::Write;
+
+use crate::{Float, NeuralNetworkModel};
+
+// 表格每列的宽度
+const COLS: [usize; 4] = [24, 16, 16, 12];
+
+impl<T: Float> NeuralNetworkModel<T> {
+    // 打印模型每层的形状和参数个数
+    pub fn summary(&self) {
+        print!("{}", self.summary_string());
+    }
+
+    // 生成keras风格的模型概要表格
+    pub fn summary_string(&self) -> String {
+        let width: usize = COLS.iter().sum::<usize>() + 1;
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", "_".repeat(width));
+        write_row(
+            &mut out,
+            ["Layer (type)", "Input Shape", "Output Shape", "Param #"],
+        );
+        let _ = writeln!(out, "{}", "=".repeat(width));
+
+        // 前一层的输出个数, 不知道输入个数时显示为?
+        let mut size = self
+            .input_size
+            .or_else(|| self.layers.first().and_then(|l| l.input_size()));
+        let mut total = 0;
+        let mut activations = 0;
+        for (i, layer) in self.layers.iter().enumerate() {
+            let input = layer.input_size().or(size);
+            let output = input.map(|n| layer.output_size(n));
+            let params = layer.param_count();
+            total += params;
+            activations += output.unwrap_or(0);
+            write_row(
+                &mut out,
+                [
+                    &format!("{}_{} ({})", layer.name().to_lowercase(), i, layer.name()),
+                    &shape(input),
+                    &shape(output),
+                    &thousands(params),
+                ],
+            );
+            size = output;
+        }
+
+        let _ = writeln!(out, "{}", "=".repeat(width));
+        let elem = std::mem::size_of::<T>();
+        let _ = writeln!(out, "Total params: {}", thousands(total));
+        let _ = writeln!(out, "Trainable params: {}", thousands(total));
+        let _ = writeln!(
+            out,
+            "Params size: {} ({} bytes per param)",
+            bytes(total * elem),
+            elem
+        );
+        // 训练时每层的输出都会缓存下来做反向传播
+        let _ = writeln!(
+            out,
+            "Forward pass size: {} per sample",
+            bytes(activations * elem)
+        );
+        let _ = writeln!(out, "{}", "_".repeat(width));
+        out
+    }
+}
+
+fn write_row(out: &mut String, cells: [&str; 4]) {
+    out.push(' ');
+    for (cell, w) in cells.iter().zip(COLS) {
+        let _ = write!(out, "{:<w$}", cell, w = w);
+    }
+    // 去掉行尾多余的空格
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+}
+
+fn shape(size: Option<usize>) -> String {
+    match size {
+        Some(n) => format!("({}, 1)", n),
+        None => "?".into(),
+    }
+}
+
+// 1234567 -> 1,234,567
+fn thousands(n: usize) -> String {
+    let s = n.to_string();
+    let mut out = String::new();
+    for (i, c) in s.chars().enumerate() {
+        if i > 0 && (s.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn bytes(n: usize) -> String {
+    if n < 1024 {
+        format!("{} B", n)
+    } else if n < 1024 * 1024 {
+        format!("{:.2} KB", n as f64 / 1024.)
+    } else {
+        format!("{:.2} MB", n as f64 / 1024. / 1024.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::NeuralNetworkModel;
+
+    use super::thousands;
+
+    #[test]
+    fn test() {
+        assert_eq!(thousands(0), "0");
+        assert_eq!(thousands(203530), "203,530");
+        assert_eq!(thousands(1234567), "1,234,567");
+
+        let model: NeuralNetworkModel = NeuralNetworkModel::sequential(784)
+            .dense_relu(256)
+            .dense_softmax(10)
+            .build()
+            .unwrap();
+        let s = model.summary_string();
+        println!("{}", s);
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(
+            lines[1],
+            " Layer (type)            Input Shape     Output Shape    Param #"
+        );
+        assert_eq!(
+            lines[3],
+            " dense_0 (Dense)         (784, 1)        (256, 1)        200,960"
+        );
+        assert_eq!(
+            lines[4],
+            " relu_1 (ReLU)           (256, 1)        (256, 1)        0"
+        );
+        assert_eq!(
+            lines[5],
+            " dense_2 (Dense)         (256, 1)        (10, 1)         2,570"
+        );
+        assert_eq!(lines[8], "Total params: 203,530");
+        assert_eq!(lines[10], "Params size: 795.04 KB (4 bytes per param)");
+        assert_eq!(lines[11], "Forward pass size: 2.08 KB per sample");
+    }
+}
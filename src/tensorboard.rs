@@ -0,0 +1,449 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mnist_data_loader::image::{DynamicImage, ImageFormat};
+use mnist_data_loader::to_img_buf;
+
+use crate::visualize::Histogram;
+use crate::{history::EpochRecord, Float, NeuralNetworkModel};
+
+// 直方图默认的桶个数
+const BUCKETS: usize = 30;
+
+/// 把训练数据写成TensorBoard的事件文件(tfevents)
+/// 文件由若干条记录组成, 每条记录是一个protobuf编码的Event:
+/// 长度(u64) | 长度的crc(u32) | 数据 | 数据的crc(u32), crc都是masked crc32c
+pub struct SummaryWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl SummaryWriter {
+    // 在log_dir下创建新的事件文件
+    pub fn new(log_dir: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::create_dir_all(log_dir.as_ref())?;
+        let now = wall_time();
+        let name = format!(
+            "events.out.tfevents.{}.hello-nn.{}",
+            now as u64,
+            std::process::id()
+        );
+        let path = log_dir.as_ref().join(name);
+        let mut w = SummaryWriter {
+            file: BufWriter::new(File::create(&path)?),
+            path,
+        };
+        // 第一条记录是文件版本
+        let mut event = vec![];
+        put_double(&mut event, 1, now);
+        put_bytes(&mut event, 3, b"brain.Event:2");
+        w.write_record(&event)?;
+        w.flush()?;
+        Ok(w)
+    }
+
+    // 事件文件的路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn add_scalar(&mut self, tag: &str, value: f64, step: i64) -> io::Result<()> {
+        let mut v = vec![];
+        put_bytes(&mut v, 1, tag.as_bytes());
+        put_float(&mut v, 2, value as f32);
+        self.write_summary(&v, step)
+    }
+
+    // values的分布, 在最小值和最大值之间等宽分桶
+    pub fn add_histogram(
+        &mut self,
+        tag: &str,
+        values: impl IntoIterator<Item = f64>,
+        step: i64,
+    ) -> io::Result<()> {
+        let values: Vec<f64> = values.into_iter().filter(|v| v.is_finite()).collect();
+        let hist = Histogram::new(values.iter().copied(), BUCKETS);
+        let counts: Vec<f64> = hist.counts.iter().map(|c| *c as f64).collect();
+
+        let mut h = vec![];
+        put_double(&mut h, 1, hist.min);
+        put_double(&mut h, 2, hist.max);
+        put_double(&mut h, 3, values.len() as f64);
+        put_double(&mut h, 4, values.iter().sum());
+        put_double(&mut h, 5, values.iter().map(|v| v * v).sum());
+        put_packed_doubles(&mut h, 6, &hist.limits());
+        put_packed_doubles(&mut h, 7, &counts);
+
+        let mut v = vec![];
+        put_bytes(&mut v, 1, tag.as_bytes());
+        put_bytes(&mut v, 5, &h);
+        self.write_summary(&v, step)
+    }
+
+    // 灰度图, pxs按行存储, 比如parse_imgs_from_reader读出来的图片
+    pub fn add_image(
+        &mut self,
+        tag: &str,
+        pxs: &[u8],
+        rows: u32,
+        cols: u32,
+        step: i64,
+    ) -> io::Result<()> {
+        let img = to_img_buf(pxs, rows, cols)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let img = DynamicImage::ImageLuma8(img);
+        let mut png = Cursor::new(vec![]);
+        img.write_to(&mut png, ImageFormat::Png)
+            .map_err(io::Error::other)?;
+
+        let mut i = vec![];
+        put_varint(&mut i, 1, rows as u64);
+        put_varint(&mut i, 2, cols as u64);
+        // 1表示灰度图
+        put_varint(&mut i, 3, 1);
+        put_bytes(&mut i, 4, png.get_ref());
+
+        let mut v = vec![];
+        put_bytes(&mut v, 1, tag.as_bytes());
+        put_bytes(&mut v, 4, &i);
+        self.write_summary(&v, step)
+    }
+
+    // 一轮训练记录里的损失, 指标和学习率, step为轮次
+    pub fn add_epoch(&mut self, record: &EpochRecord) -> io::Result<()> {
+        let step = record.epoch as i64;
+        self.add_scalar("loss", record.loss, step)?;
+        if let Some(val_loss) = record.val_loss {
+            self.add_scalar("val_loss", val_loss, step)?;
+        }
+        for (name, v) in &record.metrics {
+            self.add_scalar(name, *v, step)?;
+        }
+        self.add_scalar("learning_rate", record.learning_rate, step)
+    }
+
+    // 每层参数的直方图, 比如全连接层的 dense_0/w 和 dense_0/b
+    // 如果模型训练过, 同时写入最近一次fit的梯度 dense_0/w_grad
+    pub fn add_model_histograms<T: Float>(
+        &mut self,
+        model: &NeuralNetworkModel<T>,
+        step: i64,
+    ) -> io::Result<()> {
+        for (i, layer) in model.layers.iter().enumerate() {
+            let prefix = format!("{}_{}", layer.name().to_lowercase(), i);
+            for (k, (name, p)) in layer.params().into_iter().enumerate() {
+                let values = p.iter().map(|v| v.to_f64().unwrap());
+                self.add_histogram(&format!("{}/{}", prefix, name), values, step)?;
+                if let Some(g) = model.last_grads.get(i).and_then(|g| g.get(k)) {
+                    let values = g.iter().map(|v| v.to_f64().unwrap());
+                    self.add_histogram(&format!("{}/{}_grad", prefix, name), values, step)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    // value: 编码好的Summary.Value
+    fn write_summary(&mut self, value: &[u8], step: i64) -> io::Result<()> {
+        let mut summary = vec![];
+        put_bytes(&mut summary, 1, value);
+        let mut event = vec![];
+        put_double(&mut event, 1, wall_time());
+        put_varint(&mut event, 2, step as u64);
+        put_bytes(&mut event, 5, &summary);
+        self.write_record(&event)
+    }
+
+    fn write_record(&mut self, data: &[u8]) -> io::Result<()> {
+        let len = (data.len() as u64).to_le_bytes();
+        self.file.write_all(&len)?;
+        self.file.write_all(&masked_crc32c(&len).to_le_bytes())?;
+        self.file.write_all(data)?;
+        self.file.write_all(&masked_crc32c(data).to_le_bytes())
+    }
+}
+
+impl Drop for SummaryWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+fn wall_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0., |d| d.as_secs_f64())
+}
+
+// protobuf编码, 只实现了用到的几种类型
+// wire type: 0 varint, 1 64位, 2 变长, 5 32位
+fn put_key(buf: &mut Vec<u8>, field: u32, wire_type: u32) {
+    write_varint(buf, ((field << 3) | wire_type) as u64);
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn put_varint(buf: &mut Vec<u8>, field: u32, v: u64) {
+    put_key(buf, field, 0);
+    write_varint(buf, v);
+}
+
+fn put_double(buf: &mut Vec<u8>, field: u32, v: f64) {
+    put_key(buf, field, 1);
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_float(buf: &mut Vec<u8>, field: u32, v: f32) {
+    put_key(buf, field, 5);
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_bytes(buf: &mut Vec<u8>, field: u32, v: &[u8]) {
+    put_key(buf, field, 2);
+    write_varint(buf, v.len() as u64);
+    buf.extend_from_slice(v);
+}
+
+fn put_packed_doubles(buf: &mut Vec<u8>, field: u32, v: &[f64]) {
+    let data: Vec<u8> = v.iter().flat_map(|v| v.to_le_bytes()).collect();
+    put_bytes(buf, field, &data);
+}
+
+// crc32c(Castagnoli), 多项式0x82f63b78
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn masked_crc32c(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    crc.rotate_right(15).wrapping_add(0xa282_ead8)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use ndarray::array;
+
+    use crate::{history::EpochRecord, loss_impls::CrossEntropy, Mat, NeuralNetworkModel};
+
+    use super::{crc32c, masked_crc32c, SummaryWriter};
+
+    // 解析protobuf, 返回 (字段号, 值), 变长类型的值是原始字节, 其他类型转成u64
+    enum Field {
+        Int(u64),
+        Bytes(Vec<u8>),
+    }
+
+    type Message = Vec<(u32, Field)>;
+
+    fn read_varint(buf: &[u8], pos: &mut usize) -> u64 {
+        let mut v = 0;
+        let mut shift = 0;
+        loop {
+            let b = buf[*pos];
+            *pos += 1;
+            v |= ((b & 0x7f) as u64) << shift;
+            if b < 0x80 {
+                return v;
+            }
+            shift += 7;
+        }
+    }
+
+    fn parse(buf: &[u8]) -> Message {
+        let mut pos = 0;
+        let mut fields = vec![];
+        while pos < buf.len() {
+            let key = read_varint(buf, &mut pos);
+            let field = (key >> 3) as u32;
+            let v = match key & 7 {
+                0 => Field::Int(read_varint(buf, &mut pos)),
+                1 => {
+                    let v = u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap());
+                    pos += 8;
+                    Field::Int(v)
+                }
+                2 => {
+                    let len = read_varint(buf, &mut pos) as usize;
+                    pos += len;
+                    Field::Bytes(buf[pos - len..pos].to_vec())
+                }
+                5 => {
+                    let v = u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap());
+                    pos += 4;
+                    Field::Int(v as u64)
+                }
+                t => panic!("unknown wire type {}", t),
+            };
+            fields.push((field, v));
+        }
+        fields
+    }
+
+    fn get(fields: &[(u32, Field)], field: u32) -> Option<&Field> {
+        fields.iter().find(|(f, _)| *f == field).map(|(_, v)| v)
+    }
+
+    fn bytes(fields: &[(u32, Field)], field: u32) -> &[u8] {
+        match get(fields, field) {
+            Some(Field::Bytes(b)) => b,
+            _ => panic!("field {} is not bytes", field),
+        }
+    }
+
+    fn int(fields: &[(u32, Field)], field: u32) -> u64 {
+        match get(fields, field) {
+            Some(Field::Int(v)) => *v,
+            _ => panic!("field {} is not int", field),
+        }
+    }
+
+    // 读出所有记录并检查crc
+    fn read_records(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut records = vec![];
+        let mut pos = 0;
+        while pos < data.len() {
+            let len_bytes = &data[pos..pos + 8];
+            let len = u64::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+            let len_crc = u32::from_le_bytes(data[pos + 8..pos + 12].try_into().unwrap());
+            assert_eq!(len_crc, masked_crc32c(len_bytes));
+            let record = &data[pos + 12..pos + 12 + len];
+            let crc = u32::from_le_bytes(data[pos + 12 + len..pos + 16 + len].try_into().unwrap());
+            assert_eq!(crc, masked_crc32c(record));
+            records.push(record.to_vec());
+            pos += 16 + len;
+        }
+        records
+    }
+
+    // 返回 (step, tag, Summary.Value的字段)
+    fn read_values(data: &[u8]) -> Vec<(u64, String, Message)> {
+        read_records(data)
+            .iter()
+            .skip(1)
+            .map(|r| {
+                let event = parse(r);
+                let summary = parse(bytes(&event, 5));
+                let value = parse(bytes(&summary, 1));
+                let tag = String::from_utf8(bytes(&value, 1).to_vec()).unwrap();
+                (int(&event, 2), tag, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_crc32c() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c(b""), 0);
+    }
+
+    #[test]
+    fn test() {
+        let dir = std::env::temp_dir().join(format!("hello-nn-tb-{}", std::process::id()));
+        let mut w = SummaryWriter::new(&dir).unwrap();
+        w.add_scalar("loss", 0.25, 3).unwrap();
+        w.add_histogram("h", vec![1., 2., 2., 4.], 4).unwrap();
+        w.add_image("img", &[0, 255, 128, 64, 32, 16], 2, 3, 5)
+            .unwrap();
+        w.add_epoch(&EpochRecord {
+            epoch: 7,
+            loss: 1.,
+            val_loss: Some(2.),
+            metrics: BTreeMap::from([("val_accuracy".to_string(), 0.5)]),
+            learning_rate: 0.1,
+            duration: 0.,
+            elapsed: 0.,
+        })
+        .unwrap();
+
+        let mut model: NeuralNetworkModel = NeuralNetworkModel::sequential(2)
+            .dense_softmax(2)
+            .minimize(CrossEntropy::new())
+            .build()
+            .unwrap();
+        let datas: Vec<Mat> = vec![array![[1.], [0.]]];
+        model.fit(&datas, &datas, 0.1).unwrap();
+        w.add_model_histograms(&model, 9).unwrap();
+        w.flush().unwrap();
+
+        let data = std::fs::read(w.path()).unwrap();
+        let first = parse(&read_records(&data)[0]);
+        assert_eq!(bytes(&first, 3), b"brain.Event:2");
+
+        let values = read_values(&data);
+        let tags: Vec<&str> = values.iter().map(|(_, t, _)| t.as_str()).collect();
+        assert_eq!(
+            tags,
+            vec![
+                "loss",
+                "h",
+                "img",
+                "loss",
+                "val_loss",
+                "val_accuracy",
+                "learning_rate",
+                "dense_0/b",
+                "dense_0/b_grad",
+                "dense_0/w",
+                "dense_0/w_grad",
+            ]
+        );
+
+        let (step, _, v) = &values[0];
+        assert_eq!(*step, 3);
+        assert_eq!(f32::from_bits(int(v, 2) as u32), 0.25);
+
+        let (step, _, v) = &values[1];
+        assert_eq!(*step, 4);
+        let h = parse(bytes(v, 5));
+        assert_eq!(f64::from_bits(int(&h, 1)), 1.);
+        assert_eq!(f64::from_bits(int(&h, 2)), 4.);
+        assert_eq!(f64::from_bits(int(&h, 3)), 4.);
+        assert_eq!(f64::from_bits(int(&h, 4)), 9.);
+        assert_eq!(f64::from_bits(int(&h, 5)), 25.);
+        let counts: Vec<f64> = bytes(&h, 7)
+            .chunks(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(counts.len(), 30);
+        assert_eq!(counts.iter().sum::<f64>(), 4.);
+        assert_eq!(counts[0], 1.);
+        assert_eq!(counts[29], 1.);
+
+        let (_, _, v) = &values[2];
+        let img = parse(bytes(v, 4));
+        assert_eq!(int(&img, 1), 2);
+        assert_eq!(int(&img, 2), 3);
+        assert_eq!(&bytes(&img, 4)[1..4], b"PNG");
+
+        let (step, _, v) = &values[5];
+        assert_eq!(*step, 7);
+        assert_eq!(f32::from_bits(int(v, 2) as u32), 0.5);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
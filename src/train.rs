@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, SeedableRng};
+
+use crate::{
+    history::{EpochRecord, History},
+    metrics::accuracy,
+    Error, Float, Mat, NeuralNetworkModel, Reduction, Result,
+};
+
+// 一组样本和对应的期望结果
+pub type Samples<'a, T = f32> = (&'a [Mat<T>], &'a [Mat<T>]);
+
+/// 训练样本的来源, 按下标取出一个样本和期望结果
+/// 数据集很大时可以在这里按需读取和转换, 不用事先把所有样本都转换成Mat
+pub trait SampleSource<T: Float = f32> {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    // 第i个样本和期望结果, 读取或转换失败时返回Err, 训练中止
+    fn get(&mut self, i: usize) -> Result<(Mat<T>, Mat<T>)>;
+    // 每轮开始前调用, epoch从0开始
+    fn start_epoch(&mut self, _epoch: usize) {}
+}
+
+impl<T: Float> SampleSource<T> for Samples<'_, T> {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+    fn get(&mut self, i: usize) -> Result<(Mat<T>, Mat<T>)> {
+        Ok((self.0[i].clone(), self.1[i].clone()))
+    }
+}
+
+/// 用闭包按下标生成样本, 比如从内存映射的idx文件中读取图片
+pub struct FnSource<F> {
+    len: usize,
+    f: F,
+}
+
+impl<F> FnSource<F> {
+    pub fn new(len: usize, f: F) -> Self {
+        FnSource { len, f }
+    }
+}
+
+impl<T: Float, F: FnMut(usize) -> Result<(Mat<T>, Mat<T>)>> SampleSource<T> for FnSource<F> {
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn get(&mut self, i: usize) -> Result<(Mat<T>, Mat<T>)> {
+        (self.f)(i)
+    }
+}
+
+/// 训练参数
+#[derive(Debug, Clone)]
+pub struct TrainConfig<T: Float = f32> {
+    // 训练轮数
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: T,
+    // 每轮开始前是否打乱样本顺序
+    pub shuffle: bool,
+    // 打乱顺序用的随机种子, 每轮用 seed+轮次 初始化, 从checkpoint恢复后顺序不变
+    // 为None时使用thread_rng, 不能保存checkpoint
+    pub seed: Option<u64>,
+}
+
+impl<T: Float> Default for TrainConfig<T> {
+    fn default() -> Self {
+        TrainConfig {
+            epochs: 1,
+            batch_size: 16,
+            learning_rate: T::of(0.1),
+            shuffle: true,
+            seed: None,
+        }
+    }
+}
+
+impl<T: Float> NeuralNetworkModel<T> {
+    // 训练config.epochs轮, 返回训练历史
+    // validation: 验证集, 每轮结束后计算 val_loss 和 val_accuracy
+    pub fn train(
+        &mut self,
+        datas: &[Mat<T>],
+        labels: &[Mat<T>],
+        validation: Option<Samples<T>>,
+        config: &TrainConfig<T>,
+    ) -> Result<History> {
+        let mut history = History::new();
+        for _ in 0..config.epochs {
+            self.train_epoch(datas, labels, validation, config, &mut history)?;
+        }
+        Ok(history)
+    }
+
+    // 训练一轮, 结果追加到history中, 轮次从history已有的轮数开始计
+    pub fn train_epoch(
+        &mut self,
+        datas: &[Mat<T>],
+        labels: &[Mat<T>],
+        validation: Option<Samples<T>>,
+        config: &TrainConfig<T>,
+        history: &mut History,
+    ) -> Result<EpochRecord> {
+        if datas.len() != labels.len() {
+            return Err(Error::BatchSizeMismatch {
+                expected: datas.len(),
+                actual: labels.len(),
+            });
+        }
+        self.train_epoch_from(&mut (datas, labels), validation, config, history)
+    }
+
+    // 同train_epoch, 每个批量从source中取出样本
+    pub fn train_epoch_from(
+        &mut self,
+        source: &mut impl SampleSource<T>,
+        validation: Option<Samples<T>>,
+        config: &TrainConfig<T>,
+        history: &mut History,
+    ) -> Result<EpochRecord> {
+        if source.is_empty() || config.batch_size == 0 {
+            return Err(Error::EmptyBatch);
+        }
+        let epoch = history.epochs.len();
+        source.start_epoch(epoch);
+        let start = Instant::now();
+        let lr = config.learning_rate.to_f64().unwrap();
+
+        let mut order: Vec<usize> = (0..source.len()).collect();
+        if config.shuffle {
+            match config.seed {
+                Some(seed) => {
+                    order.shuffle(&mut StdRng::seed_from_u64(seed.wrapping_add(epoch as u64)))
+                }
+                None => order.shuffle(&mut thread_rng()),
+            }
+        }
+
+        let mut sum = 0.;
+        for (batch, idx) in order.chunks(config.batch_size).enumerate() {
+            let (data, label): (Vec<Mat<T>>, Vec<Mat<T>>) = idx
+                .iter()
+                .map(|&i| source.get(i))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .unzip();
+            let loss = self
+                .fit(&data, &label, config.learning_rate)?
+                .to_f64()
+                .unwrap();
+            // 每轮的损失是样本损失的平均值, Sum和None时批量的损失已经是和
+            sum += match self.reduction {
+                Reduction::Mean => loss * idx.len() as f64,
+                Reduction::Sum | Reduction::None => loss,
+            };
+            history.record_batch(epoch, batch, loss, lr);
+        }
+
+        let mut metrics = BTreeMap::new();
+        let mut val_loss = None;
+        if let Some((datas, labels)) = validation {
+            let (output, results) = self.evaluate_results(datas, labels)?;
+            val_loss = Some(output.loss.to_f64().unwrap());
+            metrics.insert(
+                "val_accuracy".to_string(),
+                accuracy(&results, labels) as f64,
+            );
+        }
+
+        let record = EpochRecord {
+            epoch,
+            loss: sum / source.len() as f64,
+            val_loss,
+            metrics,
+            learning_rate: lr,
+            duration: start.elapsed().as_secs_f64(),
+            elapsed: history.elapsed(),
+        };
+        history.record_epoch(record.clone());
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{
+        checkpoint::Checkpoint, history::History, loss_impls::CrossEntropy, Mat,
+        NeuralNetworkModel, Reduction, Result,
+    };
+
+    use super::{FnSource, TrainConfig};
+
+    #[test]
+    fn test() {
+        let mut model: NeuralNetworkModel = NeuralNetworkModel::sequential(2)
+            .dense_softmax(2)
+            .minimize(CrossEntropy::new())
+            .build()
+            .unwrap();
+        let datas: Vec<Mat> = vec![
+            array![[1.], [0.]],
+            array![[0.], [1.]],
+            array![[0.9], [0.1]],
+            array![[0.2], [0.8]],
+        ];
+        let labels: Vec<Mat> = vec![
+            array![[1.], [0.]],
+            array![[0.], [1.]],
+            array![[1.], [0.]],
+            array![[0.], [1.]],
+        ];
+        let config = TrainConfig {
+            epochs: 30,
+            batch_size: 3,
+            learning_rate: 0.5,
+            shuffle: true,
+            seed: None,
+        };
+        let history = model
+            .train(&datas, &labels, Some((&datas, &labels)), &config)
+            .unwrap();
+        assert_eq!(history.epochs.len(), 30);
+        assert_eq!(history.batches.len(), 60);
+        assert_eq!(history.batches[59].epoch, 29);
+        assert_eq!(history.batches[59].batch, 1);
+        let first = &history.epochs[0];
+        let last = history.last().unwrap();
+        assert!(last.loss < first.loss);
+        assert!(last.val_loss.unwrap() < first.val_loss.unwrap());
+        assert_eq!(last.metrics["val_accuracy"], 1.);
+        assert!(last.elapsed >= first.elapsed);
+    }
+
+    #[test]
+    fn test_source() {
+        let datas: Vec<Mat> = vec![array![[1.], [0.]], array![[0.], [1.]], array![[0.8], [0.3]]];
+        let config = TrainConfig {
+            batch_size: 2,
+            learning_rate: 0.5,
+            seed: Some(3),
+            ..Default::default()
+        };
+        let new_model = || -> NeuralNetworkModel {
+            NeuralNetworkModel::sequential(2)
+                .dense_softmax(2)
+                .minimize(CrossEntropy::new())
+                .build()
+                .unwrap()
+        };
+        let mut a = new_model();
+        let mut b = new_model();
+        // 让两个模型参数相同
+        Checkpoint::new(&a, &config, &History::new(), None)
+            .restore(&mut b, &mut config.clone())
+            .unwrap();
+
+        let mut h = History::new();
+        a.train_epoch(&datas, &datas, None, &config, &mut h)
+            .unwrap();
+
+        // 按需生成样本, 结果应该和直接传入切片一样
+        let mut calls = 0;
+        let mut source = FnSource::new(datas.len(), |i: usize| -> Result<(Mat, Mat)> {
+            calls += 1;
+            Ok((datas[i].clone(), datas[i].clone()))
+        });
+        let mut h2 = History::new();
+        let record = b
+            .train_epoch_from(&mut source, None, &config, &mut h2)
+            .unwrap();
+        assert_eq!(calls, 3);
+        assert_eq!(record.loss, h.epochs[0].loss);
+        assert_eq!(a.layers[0].params()[1].1, b.layers[0].params()[1].1);
+    }
+
+    #[test]
+    fn test_reduction() {
+        let datas: Vec<Mat> = vec![array![[1.], [0.]], array![[0.], [1.]], array![[0.8], [0.3]]];
+        let mut model: NeuralNetworkModel = NeuralNetworkModel::sequential(2)
+            .dense_softmax(2)
+            .minimize(CrossEntropy::new())
+            .build()
+            .unwrap();
+        // 学习率为0, 参数不变, 不同归约方式的每轮损失都是样本损失的平均值
+        let config = TrainConfig {
+            batch_size: 2,
+            learning_rate: 0.,
+            ..Default::default()
+        };
+        let mean = model
+            .train_epoch(&datas, &datas, None, &config, &mut History::new())
+            .unwrap()
+            .loss;
+        for reduction in [Reduction::Sum, Reduction::None] {
+            model.reduction = reduction;
+            let record = model
+                .train_epoch(&datas, &datas, None, &config, &mut History::new())
+                .unwrap();
+            assert!((record.loss - mean).abs() < 1e-6);
+        }
+    }
+}
@@ -1,4 +1,7 @@
-
+use std::fs::File;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::path::Path;
 
 use ndarray_rand::rand_distr::Normal;
 
@@ -7,32 +10,28 @@ use rand::{distributions::Distribution, seq::SliceRandom};
 
 use crate::{
     layer_impls::{DenseLayerNoActive, ReLULayer, SigmodLayer, SoftmaxLayer},
-    LayerCache, Mat, NeuralNetworkModel,
+    Float, LayerCache, Mat, NeuralNetworkModel,
 };
 
-impl NeuralNetworkModel {
-    pub fn push_dense_sigmod_layer(&mut self, pre_cnt: usize, cell_cnt: usize) {
+impl<T: Float> NeuralNetworkModel<T> {
+    // 没有激活函数的全连接层, 权重随机初始化
+    pub fn push_dense_layer(&mut self, pre_cnt: usize, cell_cnt: usize) {
         self.push_layer(DenseLayerNoActive::new_with(
             pre_cnt,
             cell_cnt,
-            DistCustomWrap::new(Normal::new(0., 1.).unwrap(), |v| v * 0.01),
+            DistCustomWrap::new(Normal::new(0., 1.).unwrap(), |v: f64| T::of(v * 0.01)),
         ));
+    }
+    pub fn push_dense_sigmod_layer(&mut self, pre_cnt: usize, cell_cnt: usize) {
+        self.push_dense_layer(pre_cnt, cell_cnt);
         self.push_layer(SigmodLayer::new());
     }
     pub fn push_dense_softmax_layer(&mut self, pre_cnt: usize, cell_cnt: usize) {
-        self.push_layer(DenseLayerNoActive::new_with(
-            pre_cnt,
-            cell_cnt,
-            DistCustomWrap::new(Normal::new(0., 1.).unwrap(), |v| v * 0.01),
-        ));
+        self.push_dense_layer(pre_cnt, cell_cnt);
         self.push_layer(SoftmaxLayer::new());
     }
     pub fn push_dense_relu_layer(&mut self, pre_cnt: usize, cell_cnt: usize) {
-        self.push_layer(DenseLayerNoActive::new_with(
-            pre_cnt,
-            cell_cnt,
-            DistCustomWrap::new(Normal::new(0., 1.).unwrap(), |v| v * 0.01),
-        ));
+        self.push_dense_layer(pre_cnt, cell_cnt);
         self.push_layer(ReLULayer::new());
     }
 }
@@ -40,42 +39,49 @@ impl NeuralNetworkModel {
 // 入参数组：每个样本，每层的梯度缓存
 // 累加所有样本梯度，然后求平均
 // 返回avg的梯度
-pub fn calc_all_grads_avg(all_grades: &Vec<Vec<LayerCache>>) -> Vec<LayerCache> {
+pub fn calc_all_grads_avg<T: Float>(all_grades: &[Vec<LayerCache<T>>]) -> Vec<LayerCache<T>> {
     let cnt = all_grades.len();
-    if cnt == 0 {
+    let mut base = calc_all_grads_sum(all_grades);
+
+    // 求平均
+    base.iter_mut().for_each(|v: &mut Vec<Mat<T>>| {
+        v.iter_mut().for_each(|g: &mut Mat<T>| {
+            *g = &g.view() / T::of(cnt as f64);
+        })
+    });
+
+    base
+}
+
+// 入参数组：每个样本，每层的梯度缓存
+// 返回所有样本梯度的和
+pub fn calc_all_grads_sum<T: Float>(all_grades: &[Vec<LayerCache<T>>]) -> Vec<LayerCache<T>> {
+    if all_grades.is_empty() {
         return vec![];
     }
     let mut base = all_grades[0].clone();
 
     // 从第二个样本开始，往base上累加
-    for i in 1..cnt {
+    for now in all_grades.iter().skip(1) {
         // 当前样本
-        let now = &all_grades[i];
         // 每层每层加
         for (j, layer_now) in now.iter().enumerate() {
-            let layer_base: &mut Vec<Mat> = &mut base[j];
+            let layer_base: &mut Vec<Mat<T>> = &mut base[j];
             // 每个梯度累加
             for (k, g_now) in layer_now.iter().enumerate() {
-                let g_base: &mut Mat = &mut layer_base[k];
+                let g_base: &mut Mat<T> = &mut layer_base[k];
                 *g_base = &g_base.view() + &g_now.view();
             }
         }
     }
 
-    // 求平均
-    base.iter_mut().for_each(|v: &mut Vec<Mat>| {
-        v.iter_mut().for_each(|g: &mut Mat| {
-            *g = &g.view() / cnt as f32;
-        })
-    });
-
     base
 }
 
 pub fn shuffle<A, B>(a: Vec<A>, b: Vec<B>) -> (Vec<A>, Vec<B>) {
-    let mut ab = a.into_iter().zip(b.into_iter()).collect::<Vec<(A, B)>>();
+    let mut ab = a.into_iter().zip(b).collect::<Vec<(A, B)>>();
     let mut rng = thread_rng();
-    let _shuffle = ab.shuffle(&mut rng);
+    ab.shuffle(&mut rng);
     let mut ra = Vec::with_capacity(ab.len());
     let mut rb = Vec::with_capacity(ab.len());
     for (a, b) in ab {
@@ -91,32 +97,58 @@ pub fn pause() {
 }
 
 // 处理初始化的随机数，可以对random的随机值进行转换
+// A是inner产生的随机值类型, 转换后可以是另一种精度
 #[derive(Clone)]
-pub struct DistCustomWrap<I, F> {
+pub struct DistCustomWrap<I, F, A = f32> {
     inner: I,
     f: F,
+    _a: PhantomData<fn() -> A>,
 }
 
-impl<I, F> DistCustomWrap<I, F>
+impl<I, F, A> DistCustomWrap<I, F, A>
 where
-    I: Distribution<f32>,
-    F: Fn(f32) -> f32,
+    I: Distribution<A>,
 {
     pub fn new(inner: I, f: F) -> Self {
-        DistCustomWrap { inner, f }
+        DistCustomWrap {
+            inner,
+            f,
+            _a: PhantomData,
+        }
     }
 }
 
-impl<I, F> Distribution<f32> for DistCustomWrap<I, F>
+impl<I, F, A, T> Distribution<T> for DistCustomWrap<I, F, A>
 where
-    I: Distribution<f32>,
-    F: Fn(f32) -> f32,
+    I: Distribution<A>,
+    F: Fn(A) -> T,
 {
-    fn sample<R: rand::prelude::Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+    fn sample<R: rand::prelude::Rng + ?Sized>(&self, rng: &mut R) -> T {
         (self.f)(self.inner.sample(rng))
     }
 }
 
+// 先写同目录下的临时文件并刷到磁盘, 再改名覆盖目标文件, 最后同步目录
+// 写到一半被杀掉或者断电, 已有的文件要么是旧内容要么是新内容
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp, path)?;
+    // 改名记录在目录里, 目录也要同步; 只有unix可以打开目录
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use ndarray::array;
@@ -0,0 +1,103 @@
+use mnist_data_loader::image::{Rgb, RgbImage};
+
+use super::font;
+
+pub(crate) const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+pub(crate) const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+
+// 矩形, 超出图片的部分被裁掉
+pub(crate) fn fill_rect(img: &mut RgbImage, x: i64, y: i64, w: i64, h: i64, color: Rgb<u8>) {
+    let x0 = x.max(0);
+    let y0 = y.max(0);
+    let x1 = (x + w).min(img.width() as i64);
+    let y1 = (y + h).min(img.height() as i64);
+    for py in y0..y1 {
+        for px in x0..x1 {
+            img.put_pixel(px as u32, py as u32, color);
+        }
+    }
+}
+
+// 线段, 每个点画成width x width的方块
+pub(crate) fn line(
+    img: &mut RgbImage,
+    (x0, y0): (i64, i64),
+    (x1, y1): (i64, i64),
+    width: u32,
+    color: Rgb<u8>,
+) {
+    let w = width.max(1) as i64;
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (sx, sy) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+    let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+    loop {
+        fill_rect(img, x - (w - 1) / 2, y - (w - 1) / 2, w, w, color);
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+// 文字的像素宽度, 每个字符之间空一列
+pub(crate) fn text_width(text: &str, scale: u32) -> u32 {
+    let n = text.chars().count() as u32;
+    if n == 0 {
+        0
+    } else {
+        (n * (font::WIDTH + 1) - 1) * scale
+    }
+}
+
+pub(crate) fn text_height(scale: u32) -> u32 {
+    font::HEIGHT * scale
+}
+
+// (x, y)是文字的左上角
+pub(crate) fn text(img: &mut RgbImage, x: i64, y: i64, text: &str, scale: u32, color: Rgb<u8>) {
+    let s = scale as i64;
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as i64 * (font::WIDTH as i64 + 1) * s;
+        for (row, bits) in font::glyph_or_unknown(c).iter().enumerate() {
+            for col in 0..font::WIDTH as i64 {
+                if bits & (1 << (font::WIDTH as i64 - 1 - col)) != 0 {
+                    fill_rect(img, left + col * s, y + row as i64 * s, s, s, color);
+                }
+            }
+        }
+    }
+}
+
+// 按行存储的灰度像素放大scale倍画到 (x, y), 像素不足rows x cols个时只画已有的部分
+pub(crate) fn gray(
+    img: &mut RgbImage,
+    x: i64,
+    y: i64,
+    pixels: &[u8],
+    rows: u32,
+    cols: u32,
+    scale: u32,
+) {
+    let s = scale as i64;
+    let rows = pixels.chunks(cols.max(1) as usize).take(rows as usize);
+    for (r, row) in rows.enumerate() {
+        for (c, &v) in row.iter().enumerate() {
+            fill_rect(
+                img,
+                x + c as i64 * s,
+                y + r as i64 * s,
+                s,
+                s,
+                Rgb([v, v, v]),
+            );
+        }
+    }
+}
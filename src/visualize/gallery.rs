@@ -0,0 +1,296 @@
+use std::io;
+use std::path::Path;
+
+use mnist_data_loader::image::{Rgb, RgbImage};
+use mnist_data_loader::to_img_buf;
+
+use super::{draw, font};
+use crate::{metrics::argmax, Error, Float, Mat, NeuralNetworkModel, Result};
+
+// 单元格四周的留白
+const PAD: u32 = 4;
+const RED: Rgb<u8> = Rgb([200, 0, 0]);
+
+/// 一个分类错误的样本
+#[derive(Debug, Clone, PartialEq)]
+pub struct Misclassified {
+    // 样本在数据集中的下标
+    pub index: usize,
+    pub label: usize,
+    pub predicted: usize,
+    // 模型对预测类别的输出值
+    pub confidence: f64,
+}
+
+// 找出所有分类错误的样本, 按置信度从高到低排序, 最自信的错误排在最前面
+pub fn misclassified<T: Float>(
+    model: &mut NeuralNetworkModel<T>,
+    datas: &[Mat<T>],
+    labels: &[usize],
+) -> Result<Vec<Misclassified>> {
+    if datas.len() != labels.len() {
+        return Err(Error::BatchSizeMismatch {
+            expected: datas.len(),
+            actual: labels.len(),
+        });
+    }
+    let mut errors = vec![];
+    for (index, (data, label)) in datas.iter().zip(labels).enumerate() {
+        let r = model.predict(&data.view())?;
+        let predicted = argmax(&r.view());
+        if predicted != *label {
+            errors.push(Misclassified {
+                index,
+                label: *label,
+                predicted,
+                confidence: r[(predicted, 0)].to_f64().unwrap(),
+            });
+        }
+    }
+    errors.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    Ok(errors)
+}
+
+/// 错误样本图片的显示参数
+#[derive(Debug, Clone)]
+pub struct GalleryOptions {
+    // 类别名, 为空或字体不支持时显示类别编号
+    pub class_names: Vec<String>,
+    // 图片放大的倍数
+    pub scale: u32,
+    // 拼图每行的图片个数
+    pub columns: usize,
+}
+
+impl Default for GalleryOptions {
+    fn default() -> Self {
+        GalleryOptions {
+            class_names: vec![],
+            scale: 3,
+            columns: 10,
+        }
+    }
+}
+
+impl GalleryOptions {
+    fn class_name(&self, class: usize) -> String {
+        match self.class_names.get(class) {
+            Some(name) if font::supports(name) => name.clone(),
+            _ => class.to_string(),
+        }
+    }
+}
+
+// 检查错误样本的下标都在images范围内, 且每张图片都是rows x cols个像素
+fn check_images<I: AsRef<[u8]>>(
+    errors: &[Misclassified],
+    images: &[I],
+    rows: u32,
+    cols: u32,
+) -> Result<()> {
+    let size = rows as usize * cols as usize;
+    for e in errors {
+        let pixels = images.get(e.index).ok_or_else(|| {
+            Error::InvalidParameter(format!(
+                "sample index {} is out of range for {} images",
+                e.index,
+                images.len()
+            ))
+        })?;
+        let len = pixels.as_ref().len();
+        if len != size {
+            return Err(Error::FeatureMismatch {
+                expected: size,
+                actual: (1, len),
+            });
+        }
+    }
+    Ok(())
+}
+
+// 每个错误样本保存为一张png, 文件名是 排名_下标_true-类别_pred-类别_置信度.png
+// images是原始的灰度像素, 按行存储
+pub fn save_gallery_dir<I: AsRef<[u8]>>(
+    dir: impl AsRef<Path>,
+    errors: &[Misclassified],
+    images: &[I],
+    rows: u32,
+    cols: u32,
+) -> io::Result<()> {
+    check_images(errors, images, rows, cols)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    std::fs::create_dir_all(dir.as_ref())?;
+    for (rank, e) in errors.iter().enumerate() {
+        let name = format!(
+            "{:04}_{}_true-{}_pred-{}_{:.3}.png",
+            rank, e.index, e.label, e.predicted, e.confidence
+        );
+        to_img_buf(images[e.index].as_ref(), rows, cols)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            .save(dir.as_ref().join(name))
+            .map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+// 所有错误样本拼成一张图, 每张图片下面标注真实类别, 预测类别(红色)和置信度
+pub fn montage<I: AsRef<[u8]>>(
+    errors: &[Misclassified],
+    images: &[I],
+    rows: u32,
+    cols: u32,
+    options: &GalleryOptions,
+) -> Result<RgbImage> {
+    check_images(errors, images, rows, cols)?;
+    let scale = options.scale.max(1);
+    let text_scale = if scale < 4 { 1 } else { 2 };
+    let captions: Vec<[String; 2]> = errors
+        .iter()
+        .map(|e| {
+            [
+                format!(
+                    "{}>{}",
+                    options.class_name(e.label),
+                    options.class_name(e.predicted)
+                ),
+                format!("{:.1}%", e.confidence * 100.),
+            ]
+        })
+        .collect();
+    let text_w = captions
+        .iter()
+        .flatten()
+        .map(|c| draw::text_width(c, text_scale))
+        .max()
+        .unwrap_or(0);
+    let line_h = draw::text_height(text_scale) + 2;
+    let cell_w = (cols * scale).max(text_w) + PAD * 2;
+    let cell_h = rows * scale + line_h * 2 + PAD * 3;
+
+    let columns = options.columns.clamp(1, errors.len().max(1)) as u32;
+    let grid_rows = errors.len().div_ceil(columns as usize).max(1) as u32;
+    let mut img = RgbImage::from_pixel(cell_w * columns, cell_h * grid_rows, draw::WHITE);
+    for (i, (e, caption)) in errors.iter().zip(&captions).enumerate() {
+        let x = (i as u32 % columns * cell_w + PAD) as i64;
+        let y = (i as u32 / columns * cell_h + PAD) as i64;
+        let pixels = images[e.index].as_ref();
+        draw::gray(&mut img, x, y, pixels, rows, cols, scale);
+        let ty = y + (rows * scale + PAD) as i64;
+        // 真实类别黑色, 预测类别红色
+        let advance = |t: &str| (draw::text_width(t, text_scale) + text_scale) as i64;
+        let true_name = options.class_name(e.label);
+        draw::text(&mut img, x, ty, &true_name, text_scale, draw::BLACK);
+        let tx = x + advance(&true_name);
+        draw::text(&mut img, tx, ty, ">", text_scale, draw::BLACK);
+        let pred_name = options.class_name(e.predicted);
+        draw::text(&mut img, tx + advance(">"), ty, &pred_name, text_scale, RED);
+        let ty = ty + line_h as i64;
+        draw::text(&mut img, x, ty, &caption[1], text_scale, draw::BLACK);
+    }
+    Ok(img)
+}
+
+pub fn save_montage<I: AsRef<[u8]>>(
+    path: impl AsRef<Path>,
+    errors: &[Misclassified],
+    images: &[I],
+    rows: u32,
+    cols: u32,
+    options: &GalleryOptions,
+) -> io::Result<()> {
+    montage(errors, images, rows, cols, options)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        .save(path)
+        .map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{loss_impls::CrossEntropy, Mat, NeuralNetworkModel};
+
+    use super::{misclassified, montage, save_gallery_dir, GalleryOptions, Misclassified};
+
+    #[test]
+    fn test() {
+        // 只有softmax层, 输出就是输入的softmax
+        let mut model: NeuralNetworkModel = NeuralNetworkModel::sequential(2)
+            .softmax()
+            .minimize(CrossEntropy::new())
+            .build()
+            .unwrap();
+        let datas: Vec<Mat> = vec![
+            array![[2.], [0.]],
+            array![[0.], [1.]],
+            array![[3.], [0.]],
+            array![[0.], [2.]],
+        ];
+        let errors = misclassified(&mut model, &datas, &[0, 0, 1, 1]).unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            (errors[0].index, errors[0].label, errors[0].predicted),
+            (2, 1, 0)
+        );
+        assert_eq!(errors[1].index, 1);
+        assert!(errors[0].confidence > errors[1].confidence);
+        assert!(misclassified(&mut model, &datas, &[0]).is_err());
+
+        let images = vec![vec![0u8; 4], vec![50u8; 4], vec![128u8; 4]];
+        let errors = vec![
+            Misclassified {
+                index: 1,
+                label: 3,
+                predicted: 8,
+                confidence: 0.9,
+            },
+            Misclassified {
+                index: 2,
+                label: 1,
+                predicted: 7,
+                confidence: 0.5,
+            },
+        ];
+        let options = GalleryOptions {
+            columns: 1,
+            class_names: vec!["zero".into(), "one".into()],
+            ..Default::default()
+        };
+        let img = montage(&errors, &images, 2, 2, &options).unwrap();
+        // 一列两行, 留白之后是图片的像素
+        assert!(img.height() > img.width() / 2);
+        assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(img.get_pixel(4, 4).0, [50, 50, 50]);
+        assert_eq!(img.get_pixel(4, img.height() / 2 + 4).0, [128, 128, 128]);
+        // 标注里有红色的预测类别
+        assert!(img.pixels().any(|p| p.0 == [200, 0, 0]));
+        assert_eq!(
+            montage(&[], &images, 2, 2, &options).unwrap().width(),
+            6 + 8
+        );
+
+        // 下标超出范围或像素个数不对时报错, 不会panic
+        let bad = vec![Misclassified {
+            index: 3,
+            ..errors[0].clone()
+        }];
+        let err = montage(&bad, &images, 2, 2, &options).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameter: sample index 3 is out of range for 3 images"
+        );
+        assert!(montage(&errors, &images, 2, 3, &options).is_err());
+        let short = vec![vec![0u8; 4], vec![0u8; 4], vec![0u8; 3]];
+        assert!(montage(&errors, &short, 2, 2, &options).is_err());
+        let dir = std::env::temp_dir().join(format!("hello-nn-bad-{}", std::process::id()));
+        let err = save_gallery_dir(&dir, &bad, &images, 2, 2).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!dir.exists());
+
+        let dir = std::env::temp_dir().join(format!("hello-nn-gallery-{}", std::process::id()));
+        save_gallery_dir(&dir, &errors, &images, 2, 2).unwrap();
+        assert!(dir.join("0000_1_true-3_pred-8_0.900.png").exists());
+        assert!(dir.join("0001_2_true-1_pred-7_0.500.png").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,15 @@
+mod draw;
+mod font;
+mod gallery;
+mod plot;
+mod weights;
+pub use gallery::{
+    misclassified, montage, save_gallery_dir, save_montage, GalleryOptions, Misclassified,
+};
+pub use plot::{
+    line_chart, plot_confusion, plot_history, Anchor, ConfusionOptions, Figure, PlotOptions, Series,
+};
+pub use weights::{
+    dense_filters, first_layer_filters, histogram_image, layer_histograms, model_histograms,
+    save_model_histograms, tile_kernels, Histogram, HistogramOptions, TileNorm, TileOptions,
+};
@@ -0,0 +1,649 @@
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+use mnist_data_loader::image::{Rgb, RgbImage};
+
+use super::{draw, font};
+use crate::history::History;
+use crate::metrics::ConfusionMatrix;
+
+const GRID: Rgb<u8> = Rgb([225, 225, 225]);
+const AXIS: Rgb<u8> = Rgb([100, 100, 100]);
+// 曲线的颜色, 依次使用
+const PALETTE: [Rgb<u8>; 6] = [
+    Rgb([31, 119, 180]),
+    Rgb([255, 127, 14]),
+    Rgb([44, 160, 44]),
+    Rgb([214, 39, 40]),
+    Rgb([148, 103, 189]),
+    Rgb([140, 86, 75]),
+];
+// 混淆矩阵的颜色从白到深蓝
+const COLD: [f64; 3] = [247., 251., 255.];
+const HOT: [f64; 3] = [8., 48., 107.];
+
+/// 文字的对齐方式, x是文字的左端, 中间或右端
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+#[derive(Debug, Clone)]
+enum Shape {
+    Rect {
+        x: i64,
+        y: i64,
+        w: i64,
+        h: i64,
+        color: Rgb<u8>,
+    },
+    Line {
+        from: (i64, i64),
+        to: (i64, i64),
+        width: u32,
+        color: Rgb<u8>,
+    },
+    // (x, y)是文字上边的锚点
+    Text {
+        x: i64,
+        y: i64,
+        text: String,
+        scale: u32,
+        anchor: Anchor,
+        color: Rgb<u8>,
+    },
+}
+
+/// 一张图, 记录画过的矩形, 线段和文字
+/// 可以渲染为位图(png等)或矢量图(svg), 保存时按扩展名选择
+#[derive(Debug, Clone)]
+pub struct Figure {
+    width: u32,
+    height: u32,
+    shapes: Vec<Shape>,
+}
+
+impl Figure {
+    // 白色背景的空白图
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut fig = Figure {
+            width,
+            height,
+            shapes: vec![],
+        };
+        fig.rect(0, 0, width as i64, height as i64, draw::WHITE);
+        fig
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn rect(&mut self, x: i64, y: i64, w: i64, h: i64, color: Rgb<u8>) {
+        self.shapes.push(Shape::Rect { x, y, w, h, color });
+    }
+
+    pub fn line(&mut self, from: (i64, i64), to: (i64, i64), width: u32, color: Rgb<u8>) {
+        self.shapes.push(Shape::Line {
+            from,
+            to,
+            width,
+            color,
+        });
+    }
+
+    // 位图只能显示字体里有的字符, 其他字符显示为问号
+    pub fn text(&mut self, x: i64, y: i64, text: &str, scale: u32, anchor: Anchor, color: Rgb<u8>) {
+        self.shapes.push(Shape::Text {
+            x,
+            y,
+            text: text.to_string(),
+            scale: scale.max(1),
+            anchor,
+            color,
+        });
+    }
+
+    pub fn to_image(&self) -> RgbImage {
+        let mut img = RgbImage::from_pixel(self.width, self.height, draw::WHITE);
+        for shape in &self.shapes {
+            match shape {
+                Shape::Rect { x, y, w, h, color } => {
+                    draw::fill_rect(&mut img, *x, *y, *w, *h, *color)
+                }
+                Shape::Line {
+                    from,
+                    to,
+                    width,
+                    color,
+                } => draw::line(&mut img, *from, *to, *width, *color),
+                Shape::Text {
+                    x,
+                    y,
+                    text,
+                    scale,
+                    anchor,
+                    color,
+                } => {
+                    let w = draw::text_width(text, *scale) as i64;
+                    let left = match anchor {
+                        Anchor::Start => *x,
+                        Anchor::Middle => x - w / 2,
+                        Anchor::End => x - w,
+                    };
+                    draw::text(&mut img, left, *y, text, *scale, *color);
+                }
+            }
+        }
+        img
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut s = String::new();
+        writeln!(
+            s,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            self.width, self.height
+        )
+        .unwrap();
+        for shape in &self.shapes {
+            match shape {
+                Shape::Rect { x, y, w, h, color } => writeln!(
+                    s,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                    x,
+                    y,
+                    w,
+                    h,
+                    svg_color(*color)
+                ),
+                Shape::Line {
+                    from,
+                    to,
+                    width,
+                    color,
+                } => writeln!(
+                    s,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}" stroke-linecap="square"/>"#,
+                    from.0,
+                    from.1,
+                    to.0,
+                    to.1,
+                    svg_color(*color),
+                    width
+                ),
+                Shape::Text {
+                    x,
+                    y,
+                    text,
+                    scale,
+                    anchor,
+                    color,
+                } => {
+                    let anchor = match anchor {
+                        Anchor::Start => "start",
+                        Anchor::Middle => "middle",
+                        Anchor::End => "end",
+                    };
+                    // 字号让大写字母的高度和位图字体差不多
+                    writeln!(
+                        s,
+                        r#"<text x="{}" y="{}" font-family="monospace" font-size="{}" text-anchor="{}" fill="{}">{}</text>"#,
+                        x,
+                        y + draw::text_height(*scale) as i64,
+                        font::HEIGHT * scale * 10 / 7,
+                        anchor,
+                        svg_color(*color),
+                        escape(text)
+                    )
+                }
+            }
+            .unwrap();
+        }
+        s.push_str("</svg>\n");
+        s
+    }
+
+    // 扩展名是svg时保存为矢量图, 否则按扩展名保存为位图, 比如png
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let is_svg = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("svg"));
+        if is_svg {
+            std::fs::write(path, self.to_svg())
+        } else {
+            self.to_image().save(path).map_err(io::Error::other)
+        }
+    }
+}
+
+/// 图的大小和文字的放大倍数
+#[derive(Debug, Clone)]
+pub struct PlotOptions {
+    // 学习曲线每个子图的大小
+    pub width: u32,
+    pub height: u32,
+    pub text_scale: u32,
+}
+
+impl Default for PlotOptions {
+    fn default() -> Self {
+        PlotOptions {
+            width: 480,
+            height: 320,
+            text_scale: 1,
+        }
+    }
+}
+
+/// 一条曲线, None表示这一轮没有值, 曲线在这里断开
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(f64, Option<f64>)>,
+}
+
+impl Series {
+    // 训练历史中某个指标随轮次的变化, 比如 loss, val_accuracy
+    pub fn from_history(history: &History, name: &str) -> Self {
+        let points = history
+            .epochs
+            .iter()
+            .map(|e| e.epoch as f64)
+            .zip(history.metric(name))
+            .collect();
+        Series {
+            name: name.to_string(),
+            points,
+        }
+    }
+
+    fn values(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.points
+            .iter()
+            .filter_map(|(x, y)| y.filter(|y| y.is_finite()).map(|y| (*x, y)))
+    }
+}
+
+// 折线图, 带坐标轴, 网格和图例
+pub fn line_chart(title: &str, series: &[Series], options: &PlotOptions) -> Figure {
+    let mut fig = Figure::new(options.width, options.height);
+    draw_line_chart(&mut fig, 0, 0, title, series, options);
+    fig
+}
+
+// 学习曲线: 左边是 loss 和 val_loss, 右边是所有包含accuracy的指标
+// 没有accuracy指标时只有左边的图
+pub fn plot_history(history: &History, options: &PlotOptions) -> Figure {
+    let losses: Vec<Series> = ["loss", "val_loss"]
+        .iter()
+        .map(|n| Series::from_history(history, n))
+        .filter(|s| s.values().next().is_some())
+        .collect();
+    let accuracies: Vec<Series> = history
+        .metric_names()
+        .iter()
+        .filter(|n| n.contains("accuracy"))
+        .map(|n| Series::from_history(history, n))
+        .collect();
+    let panels = if accuracies.is_empty() { 1 } else { 2 };
+    let mut fig = Figure::new(options.width * panels, options.height);
+    draw_line_chart(&mut fig, 0, 0, "loss", &losses, options);
+    if !accuracies.is_empty() {
+        let x = options.width as i64;
+        draw_line_chart(&mut fig, x, 0, "accuracy", &accuracies, options);
+    }
+    fig
+}
+
+/// 混淆矩阵的显示参数
+#[derive(Debug, Clone)]
+pub struct ConfusionOptions {
+    // 类别名, 为空或字体不支持时显示类别编号
+    pub class_names: Vec<String>,
+    // 每个格子的边长
+    pub cell: u32,
+    // 格子里显示每行的百分比, 否则显示样本个数
+    pub normalize: bool,
+    pub text_scale: u32,
+}
+
+impl Default for ConfusionOptions {
+    fn default() -> Self {
+        ConfusionOptions {
+            class_names: vec![],
+            cell: 36,
+            normalize: false,
+            text_scale: 1,
+        }
+    }
+}
+
+// 混淆矩阵, 行是真实类别, 列是预测类别
+// 颜色按每行的比例, 样本个数不均衡时也能看出每个类别的错误分布
+pub fn plot_confusion(matrix: &ConfusionMatrix, options: &ConfusionOptions) -> Figure {
+    const MARGIN: i64 = 10;
+    let scale = options.text_scale.max(1);
+    let n = matrix.classes();
+    let names: Vec<String> = (0..n)
+        .map(|i| match options.class_names.get(i) {
+            Some(name) if font::supports(name) => name.clone(),
+            _ => i.to_string(),
+        })
+        .collect();
+    let cell = options.cell as i64;
+    let line_h = draw::text_height(scale) as i64 + 6;
+    let name_w = names
+        .iter()
+        .map(|name| draw::text_width(name, scale))
+        .max()
+        .unwrap_or(0) as i64;
+    // 列上的类别名竖着写, 每个字符占一行
+    let char_h = draw::text_height(scale) as i64;
+    let step = char_h + scale as i64;
+    let name_h = names.iter().map(|n| n.chars().count()).max().unwrap_or(0) as i64 * step;
+    let title = format!("accuracy {:.2}%", matrix.accuracy() * 100.);
+    // 左边是类别名, 上面是标题, 坐标轴名字和竖排的类别名
+    let left = MARGIN + line_h + name_w + 6;
+    let top = MARGIN + line_h * 2 + name_h + 6;
+    let width =
+        (left + cell * n as i64 + MARGIN).max(draw::text_width(&title, scale) as i64 + MARGIN * 2);
+    let height = top + cell * n as i64 + MARGIN;
+    let mut fig = Figure::new(width as u32, height as u32);
+
+    let grid_mid = |i: i64| i * cell + cell / 2;
+    fig.text(MARGIN, MARGIN, &title, scale, Anchor::Start, draw::BLACK);
+    let label_y = MARGIN + line_h;
+    fig.text(
+        left + cell * n as i64 / 2,
+        label_y,
+        "predicted",
+        scale,
+        Anchor::Middle,
+        draw::BLACK,
+    );
+    // 纵轴名字逐个字母竖着写
+    let true_top = top + (cell * n as i64 - line_h * 4) / 2;
+    for (i, c) in "true".chars().enumerate() {
+        let y = true_top + i as i64 * line_h;
+        fig.text(MARGIN, y, &c.to_string(), scale, Anchor::Start, draw::BLACK);
+    }
+
+    let fractions = matrix.row_fractions();
+    for (i, name) in names.iter().enumerate() {
+        let y = top + grid_mid(i as i64) - char_h / 2;
+        fig.text(left - 6, y, name, scale, Anchor::End, draw::BLACK);
+        // 列上的类别名从下往上对齐到格子上边
+        let x = left + grid_mid(i as i64);
+        let chars: Vec<char> = name.chars().collect();
+        for (k, c) in chars.iter().enumerate() {
+            let y = top - 6 - (chars.len() - k) as i64 * step + scale as i64;
+            fig.text(x, y, &c.to_string(), scale, Anchor::Middle, draw::BLACK);
+        }
+
+        for (j, count) in matrix.counts[i].iter().enumerate() {
+            let f = fractions[i][j];
+            let color = heat(f);
+            let (x, y) = (left + j as i64 * cell, top + i as i64 * cell);
+            fig.rect(x, y, cell, cell, color);
+            let text = if options.normalize {
+                format!("{:.0}%", f * 100.)
+            } else {
+                count.to_string()
+            };
+            let text_color = if f > 0.5 { draw::WHITE } else { draw::BLACK };
+            let ty = y + cell / 2 - char_h / 2;
+            fig.text(x + cell / 2, ty, &text, scale, Anchor::Middle, text_color);
+        }
+    }
+    fig
+}
+
+fn draw_line_chart(
+    fig: &mut Figure,
+    x: i64,
+    y: i64,
+    title: &str,
+    series: &[Series],
+    options: &PlotOptions,
+) {
+    let scale = options.text_scale.max(1);
+    let char_h = draw::text_height(scale) as i64;
+    let char_w = draw::text_width("0", scale) as i64 + scale as i64;
+    let points: Vec<(f64, f64)> = series.iter().flat_map(|s| s.values()).collect();
+    let x_axis = axis(points.iter().map(|p| p.0), true);
+    let y_axis = axis(points.iter().map(|p| p.1), false);
+    let y_labels: Vec<String> = y_axis
+        .ticks
+        .iter()
+        .map(|v| format_tick(*v, y_axis.step))
+        .collect();
+    let label_w = y_labels.iter().map(|l| l.len()).max().unwrap_or(1) as i64 * char_w;
+
+    // 绘图区
+    let left = x + 12 + label_w;
+    let top = y + 16 + char_h;
+    let right = x + options.width as i64 - 12;
+    let bottom = y + options.height as i64 - 16 - char_h;
+    fig.text(left, y + 8, title, scale, Anchor::Start, draw::BLACK);
+
+    // 图例从右往左排在标题这一行
+    let mut lx = right;
+    for (k, s) in series.iter().enumerate().rev() {
+        let color = PALETTE[k % PALETTE.len()];
+        fig.text(lx, y + 8, &s.name, scale, Anchor::End, draw::BLACK);
+        lx -= draw::text_width(&s.name, scale) as i64 + 6;
+        fig.rect(lx - 12, y + 8 + char_h / 2 - 1, 12, 3, color);
+        lx -= 12 + 16;
+    }
+    if right <= left || bottom <= top {
+        return;
+    }
+    let (x_min, x_max) = (x_axis.min, x_axis.max);
+    let (y_min, y_max) = (y_axis.min, y_axis.max);
+    let px = |v: f64| left + ((v - x_min) / (x_max - x_min) * (right - left) as f64).round() as i64;
+    let py =
+        |v: f64| bottom - ((v - y_min) / (y_max - y_min) * (bottom - top) as f64).round() as i64;
+
+    for (v, label) in y_axis.ticks.iter().zip(&y_labels) {
+        let ty = py(*v);
+        fig.line((left, ty), (right, ty), 1, GRID);
+        let label_y = ty - char_h / 2;
+        fig.text(left - 6, label_y, label, scale, Anchor::End, AXIS);
+    }
+    for v in &x_axis.ticks {
+        let tx = px(*v);
+        fig.line((tx, top), (tx, bottom), 1, GRID);
+        let label = format_tick(*v, x_axis.step);
+        fig.text(tx, bottom + 6, &label, scale, Anchor::Middle, AXIS);
+    }
+    fig.line((left, bottom), (right, bottom), 1, AXIS);
+    fig.line((left, top), (left, bottom), 1, AXIS);
+
+    for (k, s) in series.iter().enumerate() {
+        let color = PALETTE[k % PALETTE.len()];
+        // 相邻两轮都有值时才连线
+        for w in s.points.windows(2) {
+            if let ((x0, Some(y0)), (x1, Some(y1))) = (w[0], w[1]) {
+                if y0.is_finite() && y1.is_finite() {
+                    fig.line((px(x0), py(y0)), (px(x1), py(y1)), 2, color);
+                }
+            }
+        }
+        for (vx, vy) in s.values() {
+            fig.rect(px(vx) - 2, py(vy) - 2, 5, 5, color);
+        }
+    }
+}
+
+/// 坐标轴的范围和刻度
+#[derive(Debug, Clone, PartialEq)]
+struct Axis {
+    min: f64,
+    max: f64,
+    ticks: Vec<f64>,
+    step: f64,
+}
+
+// 刻度的步长取1, 2, 5乘以10的幂
+// 整数轴(比如轮次)的步长至少为1, 范围就是数据的范围, 否则范围扩展到刻度上
+fn axis(values: impl Iterator<Item = f64>, integer: bool) -> Axis {
+    let (mut lo, mut hi) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+        (lo.min(v), hi.max(v))
+    });
+    if lo > hi {
+        (lo, hi) = (0., 1.);
+    }
+    if hi - lo < 1e-12 {
+        let d = if integer || lo == 0. {
+            1.
+        } else {
+            lo.abs() * 0.1
+        };
+        (lo, hi) = (lo - d, hi + d);
+    }
+    let raw = (hi - lo) / 5.;
+    let pow = 10f64.powf(raw.log10().floor());
+    let mut step = [1., 2., 5., 10.]
+        .iter()
+        .map(|m| m * pow)
+        .find(|s| *s >= raw)
+        .unwrap();
+    if integer {
+        step = step.max(1.).round();
+        let ticks = ((lo / step).ceil() as i64..=(hi / step).floor() as i64)
+            .map(|i| i as f64 * step)
+            .collect();
+        return Axis {
+            min: lo,
+            max: hi,
+            ticks,
+            step,
+        };
+    }
+    let start = (lo / step).floor() as i64;
+    let end = (hi / step).ceil() as i64;
+    let ticks: Vec<f64> = (start..=end).map(|i| i as f64 * step).collect();
+    Axis {
+        min: ticks[0],
+        max: *ticks.last().unwrap(),
+        ticks,
+        step,
+    }
+}
+
+// 小数位数由步长决定
+fn format_tick(v: f64, step: f64) -> String {
+    let decimals = (-step.log10().floor()).clamp(0., 6.) as usize;
+    format!("{:.*}", decimals, v)
+}
+
+// 0到1的比例映射到从白到深蓝的颜色
+fn heat(f: f64) -> Rgb<u8> {
+    let f = f.clamp(0., 1.);
+    let c = |i: usize| (COLD[i] + (HOT[i] - COLD[i]) * f).round() as u8;
+    Rgb([c(0), c(1), c(2)])
+}
+
+fn svg_color(c: Rgb<u8>) -> String {
+    format!("rgb({},{},{})", c.0[0], c.0[1], c.0[2])
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use crate::history::{EpochRecord, History};
+    use crate::metrics::ConfusionMatrix;
+
+    use super::*;
+
+    #[test]
+    fn test_axis() {
+        let a = axis([0.13, 0.91].into_iter(), false);
+        assert_eq!(a.step, 0.2);
+        assert!(a.min <= 0.13 && a.max >= 0.91);
+        assert_eq!((a.min, a.max), (a.ticks[0], *a.ticks.last().unwrap()));
+        assert_eq!(format_tick(0.4, a.step), "0.4");
+        // 轮次的范围就是数据的范围
+        let a = axis([1., 12.].into_iter(), true);
+        assert_eq!((a.min, a.max, a.step), (1., 12., 5.));
+        assert_eq!(a.ticks, vec![5., 10.]);
+        // 只有一个值时也有范围
+        let a = axis([2.].into_iter(), false);
+        assert!(a.min < 2. && a.max > 2.);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new();
+        for epoch in 1..=4 {
+            history.record_epoch(EpochRecord {
+                epoch,
+                loss: 1. / epoch as f64,
+                val_loss: (epoch != 2).then_some(1.2 / epoch as f64),
+                metrics: BTreeMap::from([("val_accuracy".to_string(), 0.2 * epoch as f64)]),
+                learning_rate: 0.1,
+                duration: 0.,
+                elapsed: 0.,
+            });
+        }
+        let options = PlotOptions::default();
+        let fig = plot_history(&history, &options);
+        assert_eq!((fig.width(), fig.height()), (960, 320));
+        let img = fig.to_image();
+        assert!(img.pixels().any(|p| *p == PALETTE[0]));
+        assert!(img.pixels().any(|p| *p == PALETTE[1]));
+
+        let svg = fig.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(">val_accuracy</text>"));
+        // val_loss第2轮没有值, 曲线断开, 只有3到4轮的一段
+        let val_lines = svg
+            .lines()
+            .filter(|l| l.starts_with("<line") && l.contains("rgb(255,127,14)"))
+            .count();
+        assert_eq!(val_lines, 1);
+    }
+
+    #[test]
+    fn test_confusion() {
+        let m = ConfusionMatrix::from_labels(&[0, 0, 1, 1], &[0, 0, 1, 0], 2);
+        let options = ConfusionOptions {
+            class_names: vec!["cat".into(), "dog".into()],
+            ..Default::default()
+        };
+        let fig = plot_confusion(&m, &options);
+        let img = fig.to_image();
+        assert!(img.pixels().any(|p| *p == heat(1.)));
+        assert!(img.pixels().any(|p| *p == heat(0.5)));
+        let svg = fig.to_svg();
+        assert!(svg.contains(">accuracy 75.00%</text>"));
+        assert!(svg.contains(">dog</text>"));
+
+        let dir = std::env::temp_dir().join(format!("hello-nn-plot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        fig.save(dir.join("m.svg")).unwrap();
+        fig.save(dir.join("m.png")).unwrap();
+        let svg = std::fs::read_to_string(dir.join("m.svg")).unwrap();
+        assert!(svg.ends_with("</svg>\n"));
+        let png = std::fs::read(dir.join("m.png")).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,409 @@
+use std::io;
+use std::path::Path;
+
+use mnist_data_loader::image::{Rgb, RgbImage};
+
+use super::draw;
+use crate::{Error, Float, Mat, NeuralNetworkModel, Result};
+
+const BAR: Rgb<u8> = Rgb([70, 110, 180]);
+const AXIS: Rgb<u8> = Rgb([160, 160, 160]);
+
+/// 权重映射到灰度时的归一化方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileNorm {
+    // 每个卷积核单独按最小值和最大值拉伸, 看得清每个核的形状
+    PerTile,
+    // 所有卷积核共用最小值和最大值, 可以比较不同核的强弱
+    Global,
+    // 共用最大绝对值, 0是中间的灰色, 正权重偏白, 负权重偏黑
+    Symmetric,
+}
+
+/// 权重拼图的显示参数
+#[derive(Debug, Clone)]
+pub struct TileOptions {
+    pub norm: TileNorm,
+    // 每个像素放大的倍数
+    pub scale: u32,
+    // 每行的卷积核个数, None时尽量拼成正方形
+    pub columns: Option<usize>,
+    // 卷积核之间的间隔
+    pub padding: u32,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        TileOptions {
+            norm: TileNorm::PerTile,
+            scale: 4,
+            columns: None,
+            padding: 2,
+        }
+    }
+}
+
+// 把若干个按行存储的rows x cols卷积核归一化后拼成一张图
+// 所有值都相等的核(比如死掉的神经元)显示为均匀的灰色, 核的长度不是 rows * cols 时返回Err
+pub fn tile_kernels<K: AsRef<[f64]>>(
+    kernels: &[K],
+    rows: u32,
+    cols: u32,
+    options: &TileOptions,
+) -> Result<RgbImage> {
+    let size = rows as usize * cols as usize;
+    if let Some(k) = kernels.iter().find(|k| k.as_ref().len() != size) {
+        return Err(Error::FeatureMismatch {
+            expected: size,
+            actual: (1, k.as_ref().len()),
+        });
+    }
+    let scale = options.scale.max(1);
+    let pad = options.padding;
+    let n = kernels.len();
+    let columns = options
+        .columns
+        .unwrap_or_else(|| (n as f64).sqrt().ceil() as usize)
+        .clamp(1, n.max(1));
+    let grid_rows = n.div_ceil(columns).max(1);
+    let tile_w = cols * scale + pad;
+    let tile_h = rows * scale + pad;
+    let mut img = RgbImage::from_pixel(
+        tile_w * columns as u32 + pad,
+        tile_h * grid_rows as u32 + pad,
+        draw::WHITE,
+    );
+
+    let finite = |k: &[f64]| -> Vec<f64> { k.iter().copied().filter(|v| v.is_finite()).collect() };
+    let all: Vec<f64> = kernels.iter().flat_map(|k| finite(k.as_ref())).collect();
+    let global = min_max(&all);
+    let max_abs = all.iter().fold(0., |m: f64, v| m.max(v.abs()));
+
+    for (i, kernel) in kernels.iter().enumerate() {
+        let kernel = kernel.as_ref();
+        let (lo, hi) = match options.norm {
+            TileNorm::PerTile => min_max(&finite(kernel)),
+            TileNorm::Global => global,
+            TileNorm::Symmetric => (-max_abs, max_abs),
+        };
+        let pixels: Vec<u8> = kernel.iter().map(|v| to_gray(*v, lo, hi)).collect();
+        let x = ((i % columns) as u32 * tile_w + pad) as i64;
+        let y = ((i / columns) as u32 * tile_h + pad) as i64;
+        draw::gray(&mut img, x, y, &pixels, rows, cols, scale);
+    }
+    Ok(img)
+}
+
+// 全连接层的权重w有n行j列, 每行是一个神经元对输入的模板
+// j == rows * cols 时每行画成一张rows x cols的图
+pub fn dense_filters<T: Float>(
+    w: &Mat<T>,
+    rows: u32,
+    cols: u32,
+    options: &TileOptions,
+) -> Result<RgbImage> {
+    let size = rows as usize * cols as usize;
+    if w.ncols() != size {
+        return Err(Error::FeatureMismatch {
+            expected: size,
+            actual: w.dim(),
+        });
+    }
+    let kernels: Vec<Vec<f64>> = w
+        .rows()
+        .into_iter()
+        .map(|r| r.iter().map(|v| v.to_f64().unwrap()).collect())
+        .collect();
+    tile_kernels(&kernels, rows, cols, options)
+}
+
+// 模型第一个全连接层的权重拼图, 比如28x28输入的MNIST模型
+pub fn first_layer_filters<T: Float>(
+    model: &NeuralNetworkModel<T>,
+    rows: u32,
+    cols: u32,
+    options: &TileOptions,
+) -> Result<RgbImage> {
+    let w = model
+        .layers
+        .iter()
+        .find_map(|l| l.params().into_iter().find(|(name, _)| *name == "w"))
+        .map(|(_, w)| w)
+        .ok_or_else(|| Error::InvalidArchitecture("model has no dense layer".into()))?;
+    dense_filters(w, rows, cols, options)
+}
+
+/// 等宽分桶的直方图
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub min: f64,
+    pub max: f64,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    // 在最小值和最大值之间等宽分成buckets个桶, 忽略NaN和无穷
+    // 所有值都相等时只有一个桶
+    pub fn new(values: impl IntoIterator<Item = f64>, buckets: usize) -> Self {
+        let values: Vec<f64> = values.into_iter().filter(|v| v.is_finite()).collect();
+        let (min, max) = if values.is_empty() {
+            (0., 0.)
+        } else {
+            min_max(&values)
+        };
+        let n = if max > min { buckets.max(1) } else { 1 };
+        let width = (max - min) / n as f64;
+        let mut counts = vec![0; n];
+        for v in &values {
+            let i = if width > 0. {
+                (((v - min) / width) as usize).min(n - 1)
+            } else {
+                0
+            };
+            counts[i] += 1;
+        }
+        Histogram { min, max, counts }
+    }
+
+    // 每个桶的右边界, 最后一个是max
+    pub fn limits(&self) -> Vec<f64> {
+        let n = self.counts.len();
+        let width = (self.max - self.min) / n as f64;
+        (1..=n)
+            .map(|i| {
+                if i == n {
+                    self.max
+                } else {
+                    self.min + width * i as f64
+                }
+            })
+            .collect()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+}
+
+// 每层每个参数的直方图, 名字和TensorBoard里一样, 比如 dense_0/w
+pub fn layer_histograms<T: Float>(
+    model: &NeuralNetworkModel<T>,
+    buckets: usize,
+) -> Vec<(String, Histogram)> {
+    let mut hists = vec![];
+    for (i, layer) in model.layers.iter().enumerate() {
+        let prefix = format!("{}_{}", layer.name().to_lowercase(), i);
+        for (name, p) in layer.params() {
+            let values = p.iter().map(|v| v.to_f64().unwrap());
+            hists.push((
+                format!("{}/{}", prefix, name),
+                Histogram::new(values, buckets),
+            ));
+        }
+    }
+    hists
+}
+
+/// 直方图的显示参数
+#[derive(Debug, Clone)]
+pub struct HistogramOptions {
+    pub buckets: usize,
+    // 每个直方图的大小
+    pub width: u32,
+    pub height: u32,
+    // 每行的直方图个数
+    pub columns: usize,
+}
+
+impl Default for HistogramOptions {
+    fn default() -> Self {
+        HistogramOptions {
+            buckets: 30,
+            width: 320,
+            height: 160,
+            columns: 2,
+        }
+    }
+}
+
+// 画一个直方图, 上面是标题, 下面标出最小值和最大值
+pub fn histogram_image(title: &str, hist: &Histogram, options: &HistogramOptions) -> RgbImage {
+    let mut img = RgbImage::from_pixel(options.width, options.height, draw::WHITE);
+    draw_histogram(&mut img, 0, 0, title, hist, options);
+    img
+}
+
+// 模型所有参数的直方图拼成一张图
+pub fn model_histograms<T: Float>(
+    model: &NeuralNetworkModel<T>,
+    options: &HistogramOptions,
+) -> RgbImage {
+    let hists = layer_histograms(model, options.buckets);
+    let columns = options.columns.clamp(1, hists.len().max(1));
+    let grid_rows = hists.len().div_ceil(columns).max(1);
+    let mut img = RgbImage::from_pixel(
+        options.width * columns as u32,
+        options.height * grid_rows as u32,
+        draw::WHITE,
+    );
+    for (i, (title, hist)) in hists.iter().enumerate() {
+        let x = (i % columns) as u32 * options.width;
+        let y = (i / columns) as u32 * options.height;
+        draw_histogram(&mut img, x as i64, y as i64, title, hist, options);
+    }
+    img
+}
+
+pub fn save_model_histograms<T: Float>(
+    path: impl AsRef<Path>,
+    model: &NeuralNetworkModel<T>,
+    options: &HistogramOptions,
+) -> io::Result<()> {
+    model_histograms(model, options)
+        .save(path)
+        .map_err(io::Error::other)
+}
+
+fn draw_histogram(
+    img: &mut RgbImage,
+    x: i64,
+    y: i64,
+    title: &str,
+    hist: &Histogram,
+    options: &HistogramOptions,
+) {
+    const MARGIN: i64 = 8;
+    let line_h = draw::text_height(1) as i64 + 4;
+    draw::text(img, x + MARGIN, y + MARGIN, title, 1, draw::BLACK);
+
+    // 柱子的区域
+    let left = x + MARGIN;
+    let top = y + MARGIN + line_h;
+    let w = options.width as i64 - MARGIN * 2;
+    let h = options.height as i64 - MARGIN * 2 - line_h * 2;
+    if w <= 0 || h <= 0 {
+        return;
+    }
+    let peak = hist.counts.iter().copied().max().unwrap_or(0).max(1);
+    let n = hist.counts.len() as i64;
+    for (i, c) in hist.counts.iter().enumerate() {
+        let i = i as i64;
+        let (x0, x1) = (left + w * i / n, left + w * (i + 1) / n);
+        let bar = h * *c as i64 / peak as i64;
+        // 柱子之间留一个像素
+        let bw = (x1 - x0 - 1).max(1);
+        draw::fill_rect(img, x0, top + h - bar, bw, bar, BAR);
+    }
+    draw::fill_rect(img, left, top + h, w, 1, AXIS);
+
+    let ty = top + h + 4;
+    let min = format!("{:.3}", hist.min);
+    let max = format!("{:.3}", hist.max);
+    draw::text(img, left, ty, &min, 1, draw::BLACK);
+    let max_x = left + w - draw::text_width(&max, 1) as i64;
+    draw::text(img, max_x, ty, &max, 1, draw::BLACK);
+}
+
+fn min_max(values: &[f64]) -> (f64, f64) {
+    values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+            (lo.min(*v), hi.max(*v))
+        })
+}
+
+// lo映射到0, hi映射到255, 区间为空时是中间的灰色
+fn to_gray(v: f64, lo: f64, hi: f64) -> u8 {
+    if hi > lo && v.is_finite() {
+        ((v - lo) / (hi - lo) * 255.).round().clamp(0., 255.) as u8
+    } else {
+        128
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::array;
+
+    use crate::{loss_impls::CrossEntropy, Mat, NeuralNetworkModel};
+
+    use super::*;
+
+    #[test]
+    fn test_tiles() {
+        // 两个2x2的核, 第二个全是0
+        let w: Mat<f64> = array![[-1., 0., 1., 3.], [0., 0., 0., 0.]];
+        let options = TileOptions {
+            scale: 1,
+            padding: 1,
+            columns: Some(2),
+            ..Default::default()
+        };
+        let img = dense_filters(&w, 2, 2, &options).unwrap();
+        assert_eq!((img.width(), img.height()), (7, 4));
+        assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(img.get_pixel(1, 1).0, [0, 0, 0]);
+        assert_eq!(img.get_pixel(2, 2).0, [255, 255, 255]);
+        // 全0的核是均匀的灰色
+        assert_eq!(img.get_pixel(4, 1).0, [128, 128, 128]);
+        assert_eq!(img.get_pixel(5, 2).0, [128, 128, 128]);
+
+        let options = TileOptions {
+            norm: TileNorm::Symmetric,
+            ..options
+        };
+        let img = dense_filters(&w, 2, 2, &options).unwrap();
+        assert_eq!(img.get_pixel(2, 1).0, [128, 128, 128]);
+        assert_eq!(img.get_pixel(4, 1).0, [128, 128, 128]);
+        assert_eq!(img.get_pixel(2, 2).0, [255, 255, 255]);
+
+        assert_eq!(
+            dense_filters(&w, 3, 3, &options).err(),
+            Some(Error::FeatureMismatch {
+                expected: 9,
+                actual: (2, 4)
+            })
+        );
+        // 核的长度不一致时返回Err, 不会panic
+        let kernels = vec![vec![0.; 4], vec![0.; 3]];
+        assert_eq!(
+            tile_kernels(&kernels, 2, 2, &options).err(),
+            Some(Error::FeatureMismatch {
+                expected: 4,
+                actual: (1, 3)
+            })
+        );
+    }
+
+    #[test]
+    fn test_histograms() {
+        let h = Histogram::new(vec![1., 2., 2., 4., f64::NAN], 3);
+        assert_eq!((h.min, h.max), (1., 4.));
+        assert_eq!(h.counts, vec![1, 2, 1]);
+        assert_eq!(h.limits(), vec![2., 3., 4.]);
+        assert_eq!(h.total(), 4);
+        assert_eq!(Histogram::new(vec![5., 5.], 3).counts, vec![2]);
+
+        let model: NeuralNetworkModel = NeuralNetworkModel::sequential(4)
+            .dense_relu(3)
+            .dense_softmax(2)
+            .minimize(CrossEntropy::new())
+            .build()
+            .unwrap();
+        let names: Vec<String> = layer_histograms(&model, 10)
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["dense_0/b", "dense_0/w", "dense_2/b", "dense_2/w"]
+        );
+        let options = HistogramOptions::default();
+        let img = model_histograms(&model, &options);
+        assert_eq!((img.width(), img.height()), (640, 320));
+        assert!(img.pixels().any(|p| *p == BAR));
+
+        let img = first_layer_filters(&model, 2, 2, &TileOptions::default()).unwrap();
+        assert!(img.width() > 0);
+    }
+}